@@ -0,0 +1,15 @@
+extern crate cargo_binutils as cbu;
+
+use std::process;
+
+use crate::cbu::Tool;
+
+fn main() {
+    match cbu::run(Tool::Ar, None) {
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
+        Ok(ec) => process::exit(ec),
+    }
+}
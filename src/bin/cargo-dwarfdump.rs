@@ -0,0 +1,23 @@
+extern crate cargo_binutils as cbu;
+extern crate clap;
+
+use std::process;
+
+use crate::cbu::Tool;
+
+const EXAMPLES: &str = "
+
+EXAMPLES
+
+`cargo dwarfdump --bin app -- --debug-line` - Displays the line tables
+`cargo dwarfdump --bin app -- --debug-info` - Displays the debug info entries";
+
+fn main() {
+    match cbu::run(Tool::Dwarfdump, Some(EXAMPLES)) {
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
+        Ok(ec) => process::exit(ec),
+    }
+}
@@ -14,7 +14,10 @@ EXAMPLES
 
 fn main() {
     match cbu::run(Tool::Nm, Some(EXAMPLES)) {
-        Err(e) => eprintln!("error: {}", e),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
         Ok(ec) => process::exit(ec),
     }
 }
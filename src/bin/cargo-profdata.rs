@@ -6,7 +6,10 @@ use crate::cbu::Tool;
 
 fn main() {
     match cbu::run(Tool::Profdata, None) {
-        Err(e) => eprintln!("error: {}", e),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
         Ok(ec) => process::exit(ec),
     }
 }
@@ -13,7 +13,10 @@ EXAMPLES
 
 fn main() {
     match cbu::run(Tool::Size, Some(EXAMPLES)) {
-        Err(e) => eprintln!("error: {}", e),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
         Ok(ec) => process::exit(ec),
     }
 }
@@ -0,0 +1,23 @@
+extern crate cargo_binutils as cbu;
+extern crate clap;
+
+use std::process;
+
+use crate::cbu::Tool;
+
+const EXAMPLES: &str = "
+
+EXAMPLES
+
+`cargo strings --bin app` - Lists the strings of the loaded sections
+`cargo strings --bin app -- -a -t x` - Lists the strings of the whole file with their hexadecimal offset";
+
+fn main() {
+    match cbu::run(Tool::Strings, Some(EXAMPLES)) {
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
+        Ok(ec) => process::exit(ec),
+    }
+}
@@ -12,7 +12,10 @@ EXAMPLES
 
 fn main() {
     match cbu::run(Tool::Strip, Some(EXAMPLES)) {
-        Err(e) => eprintln!("error: {}", e),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
         Ok(ec) => process::exit(ec),
     }
 }
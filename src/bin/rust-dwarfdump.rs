@@ -0,0 +1,13 @@
+extern crate cargo_binutils as cbu;
+
+use std::process;
+
+fn main() {
+    match cbu::forward("llvm-dwarfdump") {
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
+        Ok(ec) => process::exit(ec),
+    }
+}
@@ -4,7 +4,10 @@ use std::process;
 
 fn main() {
     match cbu::forward("llvm-objcopy") {
-        Err(e) => eprintln!("error: {}", e),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
         Ok(ec) => process::exit(ec),
     }
 }
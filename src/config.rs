@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// The subset of `.cargo/config` (or `.cargo/config.toml`) that we care about
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub build: Option<Build>,
+    /// The `[target.$triple]` sections
+    #[serde(default)]
+    pub target: BTreeMap<String, Target>,
+
+    /// Directory that contains the `.cargo` directory this configuration was read from
+    #[serde(skip)]
+    pub root: PathBuf,
+    /// The file this configuration was read from, if any
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// The `[build]` section
+#[derive(Debug, Default, Deserialize)]
+pub struct Build {
+    pub target: Option<String>,
+    #[serde(rename = "target-dir")]
+    pub target_dir: Option<PathBuf>,
+    pub rustflags: Option<StringList>,
+}
+
+/// A `[target.$triple]` section
+#[derive(Debug, Default, Deserialize)]
+pub struct Target {
+    pub runner: Option<StringList>,
+    pub rustflags: Option<StringList>,
+}
+
+/// A list of words, e.g. the `runner` of a target or `rustflags`: either in a single string
+/// (`"probe-rs run --chip STM32F103C8"`) or as an array
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum StringList {
+    Line(String),
+    Args(Vec<String>),
+}
+
+impl Config {
+    /// Looks for `.cargo/config` or `.cargo/config.toml` in `path` and its parent directories and
+    /// parses the first one found; like Cargo, `config` wins if a directory has both. Returns an
+    /// empty configuration if there's none.
+    pub fn get(path: &Path) -> Result<Self, failure::Error> {
+        for dir in path.ancestors() {
+            for file in &[".cargo/config", ".cargo/config.toml"] {
+                if dir.join(file).is_file() {
+                    let mut config: Config = parse(&dir.join(file))?;
+                    config.root = dir.to_owned();
+                    config.path = Some(dir.join(file));
+                    return Ok(config);
+                }
+            }
+        }
+
+        Ok(Config::default())
+    }
+
+    /// The `build.target` value
+    pub fn build_target(&self) -> Option<&str> {
+        self.build
+            .as_ref()
+            .and_then(|build| build.target.as_deref())
+    }
+
+    /// The `target.$triple.runner` value, as a command line
+    // NOTE runners declared for a `cfg(..)` expression are not considered
+    pub fn runner(&self, triple: &str) -> Option<String> {
+        match self.target.get(triple)?.runner.as_ref()? {
+            StringList::Line(line) => Some(line.clone()),
+            StringList::Args(args) => Some(args.join(" ")),
+        }
+    }
+
+    /// The `target.$triple.rustflags` value or, failing that, the `build.rustflags` one; like
+    /// Cargo, a string is split on whitespace
+    pub fn rustflags(&self, triple: &str) -> Vec<String> {
+        let flags = self
+            .target
+            .get(triple)
+            .and_then(|target| target.rustflags.as_ref())
+            .or_else(|| self.build.as_ref()?.rustflags.as_ref());
+
+        match flags {
+            Some(StringList::Line(line)) => line.split_whitespace().map(str::to_owned).collect(),
+            Some(StringList::Args(args)) => args.clone(),
+            None => vec![],
+        }
+    }
+
+    /// The `build.target-dir` value, relative paths are resolved against the directory that
+    /// contains `.cargo`, as Cargo does
+    pub fn target_dir(&self) -> Option<PathBuf> {
+        self.build
+            .as_ref()
+            .and_then(|build| build.target_dir.as_ref())
+            .map(|dir| self.root.join(dir))
+    }
+}
+
+fn parse<T>(path: &Path) -> Result<T, failure::Error>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    use std::fs::File;
+    use std::io::Read;
+    use toml::de;
+
+    let mut s = String::new();
+    File::open(path)?.read_to_string(&mut s)?;
+    Ok(de::from_str(&s)?)
+}
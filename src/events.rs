@@ -0,0 +1,79 @@
+//! `--json-events`: newline delimited JSON that describes what happens during an invocation
+//!
+//! Each line is an object whose `event` field says what happened:
+//!
+//! ``` text
+//! {"event":"build-start"}
+//! {"event":"artifact","path":"/project/target/debug/app"}
+//! {"event":"tool-start","tool":"size","command":["rust-size","app"]}
+//! {"event":"result","exit-code":0,"output":"   text    data     bss ..."}
+//! ```
+//!
+//! `build-start` and `artifact` are only emitted when the project is built. The last event is
+//! either `result`, which carries the post-processed output of the tool (lossily converted to
+//! UTF-8), or `error`, which carries the message of the error that stopped the invocation, e.g.
+//! `{"event":"error","message":"..."}`.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::{json, Value};
+
+/// Where the events go; events are dropped if `--json-events` wasn't passed
+pub struct Events<'a> {
+    out: Option<&'a mut dyn Write>,
+}
+
+impl<'a> Events<'a> {
+    pub fn new(out: &'a mut dyn Write) -> Self {
+        Events { out: Some(out) }
+    }
+
+    pub fn disabled() -> Self {
+        Events { out: None }
+    }
+
+    pub fn build_start(&mut self) -> Result<(), failure::Error> {
+        self.emit(json!({ "event": "build-start" }))
+    }
+
+    pub fn artifact(&mut self, path: &Path) -> Result<(), failure::Error> {
+        self.emit(json!({ "event": "artifact", "path": path }))
+    }
+
+    pub fn tool_start(&mut self, tool: &str, command: &Command) -> Result<(), failure::Error> {
+        let command = Some(command.get_program())
+            .into_iter()
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>();
+
+        self.emit(json!({ "event": "tool-start", "tool": tool, "command": command }))
+    }
+
+    /// The last event: the outcome of the invocation
+    pub fn result(
+        &mut self,
+        output: &[u8],
+        result: &Result<i32, failure::Error>,
+    ) -> Result<(), failure::Error> {
+        match result {
+            Ok(code) => self.emit(json!({
+                "event": "result",
+                "exit-code": code,
+                "output": String::from_utf8_lossy(output),
+            })),
+            Err(e) => self.emit(json!({ "event": "error", "message": e.to_string() })),
+        }
+    }
+
+    fn emit(&mut self, event: Value) -> Result<(), failure::Error> {
+        if let Some(out) = &mut self.out {
+            writeln!(out, "{}", event)?;
+            out.flush()?;
+        }
+
+        Ok(())
+    }
+}
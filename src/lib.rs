@@ -1,59 +1,226 @@
 #![deny(warnings)]
 
 use std::borrow::Cow;
-use std::io::{self, Write};
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, TryLockError};
+use std::hash::{Hash, Hasher};
+use std::io::{self, IsTerminal, Write};
 use std::path::{Component, Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::{env, str};
+use std::process::{Command, Output, Stdio};
+use std::str::{self, FromStr};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec;
 
 use cargo_metadata::{parse_messages, Artifact, Message};
 use clap::{App, AppSettings, Arg};
-use failure::bail;
+use failure::{bail, format_err};
 use rustc_cfg::Cfg;
 use walkdir::WalkDir;
 
+mod config;
+mod events;
 mod llvm;
+mod memory;
 mod postprocess;
+mod server;
+mod util;
+
+pub use crate::postprocess::Symbol;
+
+use crate::config::Config;
+use crate::events::Events;
+use crate::postprocess::{Demangle, DemangleBackend, SortKey};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Tool {
+    Ar,
+    Cov,
+    Cxxfilt,
+    Dwarfdump,
     Nm,
     Objcopy,
     Objdump,
     Profdata,
     Readobj,
     Size,
+    Strings,
     Strip,
 }
 
 impl Tool {
+    /// All the tools
+    pub fn all() -> &'static [Tool] {
+        &[
+            Tool::Ar,
+            Tool::Cov,
+            Tool::Cxxfilt,
+            Tool::Dwarfdump,
+            Tool::Nm,
+            Tool::Objcopy,
+            Tool::Objdump,
+            Tool::Profdata,
+            Tool::Readobj,
+            Tool::Size,
+            Tool::Strings,
+            Tool::Strip,
+        ]
+    }
+
     fn name(self) -> &'static str {
         match self {
+            Tool::Ar => "ar",
+            Tool::Cov => "cov",
+            Tool::Cxxfilt => "cxxfilt",
+            Tool::Dwarfdump => "dwarfdump",
             Tool::Nm => "nm",
             Tool::Objcopy => "objcopy",
             Tool::Objdump => "objdump",
             Tool::Profdata => "profdata",
             Tool::Readobj => "readobj",
             Tool::Size => "size",
+            Tool::Strings => "strings",
             Tool::Strip => "strip",
         }
     }
 
     // Whether this tool requires the project to be previously built
-    fn needs_build(self) -> bool {
+    fn build_requirement(self) -> BuildRequirement {
+        match self {
+            // `cov` also needs the profile, which is given along with the command, e.g.
+            // `cargo cov -- report --instr-profile=app.profdata`
+            Tool::Ar
+            | Tool::Cov
+            | Tool::Dwarfdump
+            | Tool::Nm
+            | Tool::Objcopy
+            | Tool::Objdump
+            | Tool::Size
+            | Tool::Readobj
+            | Tool::Strings
+            | Tool::Strip => BuildRequirement::Always,
+            // `profdata` works on profiles, not on artifacts, but the instrumented binary is
+            // usually needed in the same workflow
+            Tool::Profdata => BuildRequirement::Optional,
+            // `cxxfilt` demangles the symbols it's given, as arguments or on stdin
+            Tool::Cxxfilt => BuildRequirement::Never,
+        }
+    }
+
+    // Whether `--output` is the file this tool produces, e.g. the merged profile of `profdata`,
+    // rather than where its stdout goes
+    fn produces_output(self) -> bool {
+        matches!(self, Tool::Objcopy | Tool::Profdata | Tool::Strip)
+    }
+
+    // Whether this tool is implemented here rather than by an LLVM tool: the `llvm-tools-preview`
+    // component doesn't ship `llvm-cxxfilt`
+    fn is_builtin(self) -> bool {
+        self == Tool::Cxxfilt
+    }
+
+    // Whether the output of this tool is passed along untouched
+    fn passes_output_through(self) -> bool {
         match self {
-            Tool::Nm | Tool::Objcopy | Tool::Objdump | Tool::Size | Tool::Readobj | Tool::Strip => true,
-            Tool::Profdata /* ? */ => false,
+            Tool::Ar
+            | Tool::Cov
+            | Tool::Cxxfilt
+            | Tool::Dwarfdump
+            | Tool::Objcopy
+            | Tool::Profdata
+            | Tool::Strings
+            | Tool::Strip => true,
+            Tool::Nm | Tool::Objdump | Tool::Readobj | Tool::Size => false,
         }
     }
 }
 
+/// Whether a tool operates on a build artifact
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BuildRequirement {
+    /// The tool never operates on an artifact; the build flags are not accepted
+    Never,
+    /// The project is always built and the artifact is passed to the tool
+    Always,
+    /// The project is only built if an artifact (e.g. `--bin`) is explicitly selected
+    Optional,
+}
+
+/// Byte order of a target
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Endian {
     Little,
     Big,
 }
 
+impl Endian {
+    // `target_endian` is either `little` or `big`
+    fn of(cfg: &Cfg) -> Self {
+        if cfg.target_endian == "big" {
+            Endian::Big
+        } else {
+            Endian::Little
+        }
+    }
+}
+
+/// Format of the post-processed output of `nm` and `size`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageFormat {
+    /// The tool's own output, e.g. a table for `size`
+    Human,
+    /// An array of objects, one per row of the tool's output
+    Json,
+    /// Comma separated values with a header line
+    Csv,
+}
+
+impl FromStr for MessageFormat {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, failure::Error> {
+        Ok(match s {
+            "human" => MessageFormat::Human,
+            "json" => MessageFormat::Json,
+            "csv" => MessageFormat::Csv,
+            _ => bail!(
+                "unknown message format `{}`; expected one of `human`, `json` or `csv`",
+                s
+            ),
+        })
+    }
+}
+
+/// Exit code of `cargo size` when `--flash-max` or `--ram-max` is exceeded. Failures of the tool
+/// or of `cargo-binutils` itself exit with a different non-zero code
+pub const BUDGET_EXCEEDED: i32 = 2;
+
+/// `cargo build` failed; Cargo has already reported why. `run` exits with the exit code of Cargo
+#[derive(Debug)]
+struct BuildFailed {
+    code: i32,
+}
+
+impl fmt::Display for BuildFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`cargo build` failed with exit code {}", self.code)
+    }
+}
+
+impl failure::Fail for BuildFailed {}
+
+/// The output of `Context::run_tool` and `run_capture`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolOutput {
+    /// Post-processed standard output of the tool
+    pub stdout: Vec<u8>,
+    pub exit_code: i32,
+}
+
 /// Execution context
 // TODO this should be some sort of initialize once, read-only singleton
 pub struct Context {
@@ -62,39 +229,23 @@ pub struct Context {
     target: String,
 }
 
-/// Search for `file` in `path` and its parent directories
-fn search<'p>(path: &'p Path, file: &str) -> Option<&'p Path> {
-    path.ancestors().find(|dir| dir.join(file).exists())
-}
-
-fn parse<T>(path: &Path) -> Result<T, failure::Error>
-where
-    T: for<'de> serde::Deserialize<'de>,
-{
-    use std::fs::File;
-    use std::io::Read;
-    use toml::de;
-
-    let mut s = String::new();
-    File::open(path)?.read_to_string(&mut s)?;
-    Ok(de::from_str(&s)?)
-}
-
 impl Context {
     /* Constructors */
     /// Get a context structure from a built artifact.
-    fn from_artifact(artifact: &Artifact) -> Result<Self, failure::Error> {
+    fn from_artifact(
+        artifact: &Artifact,
+        target_dir: &Path,
+        profile_dir: &str,
+    ) -> Result<Self, failure::Error> {
         // Get target from artifact. Ideally, the artifact should really contain
         // the target triple. Sadly, it doesn't. So as an approximation, we
         // extract it from the filename path.
-        let metadata = cargo_metadata::MetadataCommand::new().exec()?;
 
         // Should always succeed.
-        let target_path = artifact.filenames[0].strip_prefix(metadata.target_directory)?;
+        let target_path = artifact.filenames[0].strip_prefix(target_dir)?;
         let target_name = if let Some(Component::Normal(path)) = target_path.components().next() {
             let path = path.to_string_lossy();
-            // TODO: How will custom profiles impact this?
-            if path == "debug" || path == "release" {
+            if path == profile_dir {
                 // Looks like this artifact was built for the host.
                 rustc_version::version_meta()?.host
             } else {
@@ -110,34 +261,52 @@ impl Context {
 
     /// Get a context structure from a provided target flag, used when cargo
     /// was not used to build the binary.
-    fn from_flag(target_flag: Option<&str>) -> Result<Self, failure::Error> {
-        let metadata = cargo_metadata::MetadataCommand::new().exec().ok();
+    pub fn from_flag(target_flag: Option<&str>) -> Result<Self, failure::Error> {
+        Self::from_metadata(
+            cargo_metadata::MetadataCommand::new(),
+            &env::current_dir()?,
+            target_flag,
+        )
+    }
+
+    /// Get a context structure for the project whose manifest is at `manifest_path`, rather than
+    /// for the project in the current directory; `target` plays the role of the target flag.
+    pub fn for_manifest(
+        manifest_path: &Path,
+        target: Option<&str>,
+    ) -> Result<Self, failure::Error> {
+        let mut metadata = cargo_metadata::MetadataCommand::new();
+        metadata.manifest_path(manifest_path);
+
+        let dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+        Self::from_metadata(metadata, dir, target)
+    }
+
+    /// Resolves the target of the workspace described by `metadata` or, if that's not a Cargo
+    /// project, of the directory `dir`
+    fn from_metadata(
+        mut metadata: cargo_metadata::MetadataCommand,
+        dir: &Path,
+        target_flag: Option<&str>,
+    ) -> Result<Self, failure::Error> {
+        let metadata = metadata.exec().ok();
 
         let meta = rustc_version::version_meta()?;
         let host = meta.host;
         let host_target_name = host;
 
-        // Get the "default" target override in .cargo/config.
-        let mut config_target_name = None;
-        let config: toml::Value;
-
         let root_dir = if let Some(metadata) = metadata {
             metadata.workspace_root
         } else {
-            std::env::current_dir()?
+            dir.to_owned()
         };
 
-        if let Some(path) = search(&root_dir, ".cargo/config") {
-            config = parse(&path.join(".cargo/config"))?;
-            config_target_name = config
-                .get("build")
-                .and_then(|build| build.get("target"))
-                .and_then(|target| target.as_str());
-        }
+        // Get the "default" target override in .cargo/config.
+        let config = Config::get(&root_dir)?;
 
         // Find the actual target.
         let target_name = target_flag
-            .or(config_target_name)
+            .or_else(|| config.build_target())
             .unwrap_or(&host_target_name);
 
         Self::from_target_name(target_name)
@@ -152,22 +321,123 @@ impl Context {
         })
     }
 
+    /// Returns the tools whose LLVM tool (e.g. `llvm-nm` for `Tool::Nm`) is installed
+    pub fn available_tools(&self) -> Result<Vec<Tool>, failure::Error> {
+        let bindir = bindir()?;
+
+        Ok(Tool::all()
+            .iter()
+            .cloned()
+            .filter(|tool| {
+                tool.is_builtin()
+                    || bindir
+                        .join(&*exe(&format!("llvm-{}", tool.name())))
+                        .exists()
+            })
+            .collect())
+    }
+
+    /// The byte order of the target
+    pub fn endian(&self) -> Endian {
+        Endian::of(&self.cfg)
+    }
+
     fn rustc_cfg(&self) -> &Cfg {
         &self.cfg
     }
 
-    fn tool(&self, tool: Tool, target: &str) -> Command {
+    /// Runs `tool` on `artifact`, if any, followed by `args` and returns its output, post-processed
+    /// as the Cargo subcommands print it
+    pub fn run_tool(
+        &self,
+        tool: Tool,
+        artifact: Option<&Path>,
+        args: &[&str],
+    ) -> Result<ToolOutput, failure::Error> {
+        if tool == Tool::Cxxfilt {
+            // the "artifact" is the first symbol
+            let symbols = artifact
+                .map(|symbol| symbol.to_string_lossy().into_owned())
+                .into_iter()
+                .chain(args.iter().map(|&arg| arg.to_owned()))
+                .map(|symbol| symbol + "\n")
+                .collect::<String>();
+
+            return Ok(ToolOutput {
+                stdout: postprocess::demangle(symbols.as_bytes(), Demangle::default()).into_owned(),
+                exit_code: 0,
+            });
+        }
+
+        let output = util::output(
+            self.tool_command(tool, artifact, args)
+                .stderr(Stdio::inherit()),
+        )?;
+
+        if tool == Tool::Size {
+            for line in postprocess::diagnostics(&output.stdout) {
+                eprintln!("{}", line);
+            }
+        }
+
+        Ok(ToolOutput {
+            stdout: postprocess(
+                tool,
+                &output.stdout,
+                MessageFormat::Human,
+                Demangle::default(),
+            )
+            .into_owned(),
+            exit_code: util::exit_code(output.status),
+        })
+    }
+
+    /// Runs `nm` on `artifact` and returns its symbols, demangled
+    pub fn symbols(&self, artifact: &Path) -> Result<vec::IntoIter<Symbol>, failure::Error> {
+        // `-S` adds the sizes
+        let output = util::output(
+            self.tool_command(Tool::Nm, Some(artifact), &["-S"])
+                .stderr(Stdio::inherit()),
+        )?;
+        if !output.status.success() {
+            bail!("`nm` couldn't list the symbols of `{}`", artifact.display());
+        }
+
+        Ok(postprocess::symbols(&output.stdout, Demangle::default()).into_iter())
+    }
+
+    /// The invocation used by `run_tool`
+    fn tool_command(&self, tool: Tool, artifact: Option<&Path>, args: &[&str]) -> Command {
+        let mut lltool = self.tool(tool, &self.target, true);
+        if tool == Tool::Readobj {
+            lltool.arg("-elf-output-style=GNU");
+        }
+        if let Some(artifact) = artifact {
+            lltool.arg(artifact);
+        }
+        lltool.args(args);
+
+        lltool
+    }
+
+    /// `arch` controls whether the architecture of the target is passed to `objdump`
+    fn tool(&self, tool: Tool, target: &str, arch: bool) -> Command {
         let mut c = Command::new(format!("rust-{}", tool.name()));
 
-        if tool == Tool::Objdump {
+        if tool == Tool::Objdump && arch {
             let arch_name = llvm::arch_name(self.rustc_cfg(), target);
 
             if arch_name == "thumb" {
-                // `-arch-name=thumb` doesn't produce the right output so instead we pass
-                // `-triple=$target`, which contains more information about the target
-                c.args(&["-triple", target]);
+                // `--arch-name=thumb` doesn't produce the right output so instead we pass
+                // `--triple=$target`, which contains more information about the target
+                c.arg(format!("--triple={}", target));
             } else {
-                c.args(&["-arch-name", arch_name]);
+                // the triple carries more information than the architecture (e.g. the
+                // sub-architecture) so we pass it as well, when we know it
+                if let Some(triple) = llvm::triple(target) {
+                    c.arg(format!("--triple={}", triple));
+                }
+                c.arg(format!("--arch-name={}", arch_name));
             }
         }
 
@@ -175,314 +445,3145 @@ impl Context {
     }
 }
 
-#[cfg(target_os = "windows")]
-fn exe(name: &str) -> Cow<str> {
-    format!("{}.exe", name).into()
+// The `--manifest-path` of the current invocation, as an absolute path. Like the toolchain
+// selected with `+toolchain`, this applies to everything the invocation runs
+static MANIFEST_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+fn manifest_path() -> Option<PathBuf> {
+    MANIFEST_PATH.lock().unwrap().clone()
 }
 
-#[cfg(not(target_os = "windows"))]
-fn exe(name: &str) -> Cow<str> {
-    name.into()
+/// `cargo metadata` for the project being inspected
+fn metadata_command() -> cargo_metadata::MetadataCommand {
+    let mut metadata = cargo_metadata::MetadataCommand::new();
+    if let Some(path) = manifest_path() {
+        metadata.manifest_path(path);
+    }
+    metadata
 }
 
-fn determine_artifact(matches: &clap::ArgMatches) -> Result<Option<Artifact>, failure::Error> {
-    let verbose = matches.is_present("verbose");
-    let target_flag = matches.value_of("target");
+/// The directory of the project being inspected: that of `--manifest-path` or else the CWD
+fn project_dir() -> Result<PathBuf, failure::Error> {
+    match manifest_path() {
+        Some(path) => Ok(path.parent().unwrap().to_owned()),
+        None => Ok(env::current_dir()?),
+    }
+}
 
-    fn at_least_two_are_true(a: bool, b: bool, c: bool) -> bool {
-        if a {
-            b || c
-        } else {
-            b && c
-        }
+/// The `Context` of the project being inspected
+fn project_context(target_flag: Option<&str>) -> Result<Context, failure::Error> {
+    match manifest_path() {
+        Some(path) => Context::for_manifest(&path, target_flag),
+        None => Context::from_flag(target_flag),
     }
+}
 
-    let bin = matches.is_present("bin");
-    let example = matches.is_present("example");
-    let lib = matches.is_present("lib");
+// `CARGO_BUILD_TARGET_DIR` is the environment form of `build.target-dir`; `CARGO_TARGET_DIR` wins
+fn target_dir_env() -> Option<OsString> {
+    env::var_os("CARGO_TARGET_DIR").or_else(|| env::var_os("CARGO_BUILD_TARGET_DIR"))
+}
 
-    if at_least_two_are_true(bin, example, lib) {
-        bail!("Only one of `--bin`, `--example` or `--lib` must be specified")
+/// The flags Cargo passes to `rustc` when building for `target`, following Cargo's precedence:
+/// `CARGO_ENCODED_RUSTFLAGS`, `RUSTFLAGS`, `target.$triple.rustflags` and then `build.rustflags`
+fn rustflags(target: &str) -> Result<Vec<String>, failure::Error> {
+    if let Ok(flags) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+        return Ok(flags
+            .split('\x1f')
+            .filter(|flag| !flag.is_empty())
+            .map(str::to_owned)
+            .collect());
     }
 
-    let mut cargo = Command::new("cargo");
-    cargo.arg("build");
+    if let Ok(flags) = env::var("RUSTFLAGS") {
+        return Ok(flags.split_whitespace().map(str::to_owned).collect());
+    }
 
-    // NOTE we do *not* use `project.target()` here because Cargo will figure things out on
-    // its own (i.e. it will search and parse .cargo/config, etc.)
-    if let Some(target) = target_flag {
-        cargo.args(&["--target", target]);
+    Ok(Config::get(&project_dir()?)?.rustflags(target))
+}
+
+/// Resolves the directory where Cargo places its build artifacts
+///
+/// This follows Cargo's own precedence: the `--target-dir` flag, then the `CARGO_TARGET_DIR` (or
+/// `CARGO_BUILD_TARGET_DIR`) environment variable, then `build.target-dir` in `.cargo/config` and
+/// finally the `target` directory at the root of the workspace.
+fn target_dir(target_dir_flag: Option<&str>) -> Result<PathBuf, failure::Error> {
+    let cwd = env::current_dir()?;
+
+    if let Some(dir) = target_dir_flag {
+        return Ok(cwd.join(dir));
     }
 
-    if matches.is_present("all-features") {
-        cargo.arg("--all-features");
-    } else if let Some(features) = matches.value_of("features") {
-        cargo.args(&["--features", features]);
+    if let Some(dir) = target_dir_env() {
+        return Ok(cwd.join(dir));
     }
 
-    let artifact_name = if bin {
-        let bin_name = matches.value_of("bin").unwrap();
-        cargo.args(&["--bin", bin_name]);
-        bin_name
-    } else if example {
-        let example_name = matches.value_of("example").unwrap();
-        cargo.args(&["--example", example_name]);
-        example_name
-    } else if lib {
-        let lib_name = matches.value_of("lib").unwrap();
-        cargo.args(&["--lib", lib_name]);
-        lib_name
-    } else {
-        ""
+    let root_dir = match metadata_command().no_deps().exec() {
+        Ok(metadata) => metadata.workspace_root,
+        Err(_) => project_dir()?,
     };
 
-    if matches.is_present("release") {
-        cargo.arg("--release");
+    if let Some(dir) = Config::get(&root_dir)?.target_dir() {
+        return Ok(dir);
     }
 
-    cargo.arg("--message-format=json");
-    cargo.stdout(Stdio::piped());
+    Ok(root_dir.join("target"))
+}
 
-    if verbose {
-        eprintln!("{:?}", cargo);
+/// The target given with `--target` or, failing that, the first line of the `--target-file`
+fn target_flag(matches: &clap::ArgMatches) -> Result<Option<String>, failure::Error> {
+    let file = if let Some(path) = matches.value_of("target-file") {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format_err!("couldn't read the target file `{}`: {}", path, e))?;
+        match contents.lines().next().map(str::trim) {
+            Some(target) if !target.is_empty() => Some(target.to_owned()),
+            _ => bail!("the target file `{}` is empty", path),
+        }
+    } else {
+        None
+    };
+
+    match (matches.value_of("target"), file) {
+        (Some(flag), Some(file)) if flag != file => bail!(
+            "`--target {}` and the `--target-file` (`{}`) disagree on the target",
+            flag,
+            file
+        ),
+        (Some(flag), _) => Ok(Some(flag.to_owned())),
+        (None, None) if matches.is_present("auto-target") => auto_target(),
+        (None, file) => Ok(file),
     }
+}
 
-    let mut child = cargo.spawn()?;
-    let stdout = child.stdout.take().expect("Pipe to cargo process failed");
+/// Checks that `rustc` knows `target`, up front, as the errors of a build for an unknown target
+/// don't say much. The closest built-in target is suggested
+fn check_target(target: &str) -> Result<(), failure::Error> {
+    let output = util::output(rustc().args(["--print", "target-list"]))?;
+    if !output.status.success() {
+        // `rustc` will complain about the target, if need be
+        return Ok(());
+    }
 
-    let mut wanted_artifact = None;
-    for message in parse_messages(stdout) {
-        match message? {
-            Message::CompilerArtifact(artifact) => {
-                if artifact.target.name == artifact_name
-                    || artifact_name.is_empty() && artifact.executable.is_some()
-                {
-                    if wanted_artifact.is_some() {
-                        bail!("Can only have one matching artifact but found several");
-                    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let targets = stdout.lines().map(str::trim).collect::<Vec<_>>();
+    // custom targets, i.e. specification files, are not listed
+    if targets.contains(&target) || Cfg::of(target).is_ok() {
+        return Ok(());
+    }
 
-                    wanted_artifact = Some(artifact.clone());
-                }
-            }
-            Message::CompilerMessage(msg) => {
-                if let Some(rendered) = msg.message.rendered {
-                    print!("{}", rendered);
-                }
-            }
-            _ => (),
+    let closest = targets
+        .iter()
+        .map(|known| (util::edit_distance(target, known), known))
+        .min();
+    match closest {
+        Some((distance, known)) if distance <= 3 => {
+            bail!("unknown target `{}`; did you mean `{}`?", target, known)
         }
+        _ => bail!(
+            "unknown target `{}`; run `rustc --print target-list` to list the built-in targets",
+            target
+        ),
     }
+}
 
-    let status = child.wait()?;
-    if !status.success() {
-        bail!("Failed to parse crate metadata");
+/// The only target, besides the host, installed with rustup. Returns `None` if the Cargo
+/// configuration sets `build.target`, which takes precedence
+fn auto_target() -> Result<Option<String>, failure::Error> {
+    if Config::get(&project_dir()?)?.build_target().is_some() {
+        return Ok(None);
     }
 
-    if wanted_artifact.is_none() {
-        bail!("Could not determine the wanted artifact");
+    let host = rustc_version::version_meta()?.host;
+    let output = util::output(Command::new("rustup").args(["target", "list", "--installed"]))?;
+    if !output.status.success() {
+        bail!(
+            "`rustup target list --installed` failed; pass `--target` instead of `--auto-target`"
+        );
     }
 
-    Ok(wanted_artifact)
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let targets = stdout
+        .lines()
+        .map(str::trim)
+        .filter(|target| !target.is_empty() && *target != host)
+        .collect::<Vec<_>>();
+    match targets[..] {
+        [target] => Ok(Some(target.to_owned())),
+        [] => bail!(
+            "`--auto-target` found no installed target besides the host (`{}`); pass `--target`",
+            host
+        ),
+        _ => bail!(
+            "`--auto-target` found several installed targets ({}); pass `--target` to pick one",
+            targets.join(", ")
+        ),
+    }
 }
 
-pub fn run(tool: Tool, examples: Option<&str>) -> Result<i32, failure::Error> {
-    let name = tool.name();
-    let needs_build = tool.needs_build();
-
-    let app = App::new(format!("cargo-{}", name));
-    let about = format!(
-        "Proxy for the `llvm-{}` tool shipped with the Rust toolchain.",
-        name
-    );
-    let after_help = format!(
-        "\
-The arguments specified *after* the `--` will be passed to the proxied tool invocation.
-
-To see all the flags the proxied tool accepts run `cargo-{} -- -help`.{}",
-        name,
-        examples.unwrap_or("")
-    );
-    let app = app
-        .about(&*about)
-        .version(env!("CARGO_PKG_VERSION"))
-        .setting(AppSettings::TrailingVarArg)
-        .setting(AppSettings::DontCollapseArgsInUsage)
-        // as this is used as a Cargo subcommand the first argument will be the name of the binary
-        // we ignore this argument
-        .arg(Arg::with_name("binary-name").hidden(true))
-        .arg(
-            Arg::with_name("target")
-                .long("target")
-                .takes_value(true)
-                .value_name("TRIPLE")
-                .help("Target triple for which the code is compiled"),
-        )
-        .arg(
-            Arg::with_name("verbose")
-                .long("verbose")
-                .short("v")
-                .help("Use verbose output"),
-        )
-        .arg(Arg::with_name("--").short("-").hidden_short_help(true))
-        .arg(Arg::with_name("args").multiple(true))
-        .after_help(&*after_help);
-
-    let matches = if needs_build {
-        app.arg(
-            Arg::with_name("bin")
-                .long("bin")
-                .takes_value(true)
-                .value_name("NAME")
-                .help("Build only the specified binary"),
-        )
-        .arg(
-            Arg::with_name("example")
-                .long("example")
-                .takes_value(true)
-                .value_name("NAME")
-                .help("Build only the specified example"),
-        )
-        .arg(
-            Arg::with_name("lib")
-                .long("lib")
-                .help("Build only this package's library"),
-        )
-        .arg(
-            Arg::with_name("release")
-                .long("release")
-                .help("Build artifacts in release mode, with optimizations"),
-        )
-        .arg(
-            Arg::with_name("features")
-                .long("features")
-                .takes_value(true)
-                .value_name("FEATURES")
-                .help("Space-separated list of features to activate"),
-        )
-        .arg(
-            Arg::with_name("all-features")
-                .long("all-features")
-                .takes_value(false)
-                .help("Activate all available features"),
-        )
+/// Name of the profile selected by the `--release` and `--profile` flags
+fn profile<'a>(matches: &'a clap::ArgMatches) -> &'a str {
+    if let Some(profile) = matches.value_of("profile") {
+        profile
+    } else if matches.is_present("release") || matches.is_present("max") {
+        "release"
     } else {
-        app
-    }
-    .get_matches();
-
-    let verbose = matches.is_present("verbose");
-    let target_flag = matches.value_of("target");
-
-    // Figure out which artifact to use with the tool
-    let artifact = determine_artifact(&matches)?;
-
-    let mut tool_args = vec![];
-    if let Some(arg) = matches.value_of("--") {
-        tool_args.push(arg);
+        "dev"
     }
+}
 
-    if let Some(args) = matches.values_of("args") {
-        tool_args.extend(args);
+/// Directory, within the target directory, where Cargo places the artifacts built with the
+/// selected profile. This mirrors Cargo: the built-in `dev` and `test` profiles use `debug`, `bench`
+/// uses `release` and every other profile uses its own name
+fn profile_dir<'a>(matches: &'a clap::ArgMatches) -> &'a str {
+    match profile(matches) {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
     }
+}
 
-    let ctxt = if let Some(artifact) = &artifact {
-        Context::from_artifact(artifact)?
+/// Parses a memory address, either in hexadecimal (`0x` prefixed) or in decimal
+fn parse_address(s: &str) -> Option<u64> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
     } else {
-        Context::from_flag(target_flag)?
-    };
+        s.parse().ok()
+    }
+}
 
-    let mut lltool = ctxt.tool(tool, &ctxt.target);
+fn validate_address(s: String) -> Result<(), String> {
+    parse_address(&s)
+        .map(drop)
+        .ok_or_else(|| format!("`{}` is not a valid address", s))
+}
 
-    // Extra flags
+/// Post-processes the `stdout` of `tool`
+fn postprocess(
+    tool: Tool,
+    stdout: &[u8],
+    message_format: MessageFormat,
+    demangle: Demangle,
+) -> Cow<'_, [u8]> {
     match tool {
-        Tool::Readobj => {
-            // The default output style of `readobj` is JSON-like, which is not user friendly, so we
-            // change it to the human readable GNU style
-            lltool.arg("-elf-output-style=GNU");
-        }
-        Tool::Nm | Tool::Objcopy | Tool::Objdump | Tool::Profdata | Tool::Size | Tool::Strip => {}
+        Tool::Objdump | Tool::Readobj => postprocess::demangle(stdout, demangle),
+        Tool::Nm => postprocess::nm(stdout, message_format, demangle),
+        Tool::Size => postprocess::size(stdout, message_format),
+        Tool::Ar
+        | Tool::Cov
+        | Tool::Cxxfilt
+        | Tool::Dwarfdump
+        | Tool::Objcopy
+        | Tool::Profdata
+        | Tool::Strings
+        | Tool::Strip => stdout.into(),
     }
+}
 
-    // Artifact
-    if let Some(artifact) = &artifact {
-        let file = match &artifact.executable {
-            // Example and bins have an executable
-            Some(val) => val,
-            // Libs have an rlib and an rmeta. We want the rlib, which always
-            // comes first in the filenames array after some quick testing.
-            //
-            // We could instead look for files ending in .rlib, but that would
-            // fail for cdylib and other fancy crate kinds.
-            None => &artifact.filenames[0],
-        };
+#[cfg(target_os = "windows")]
+fn exe(name: &str) -> Cow<'_, str> {
+    format!("{}.exe", name).into()
+}
 
-        match tool {
-            // for some tools we change the CWD (current working directory) and
-            // make the artifact path relative. This makes the path that the
-            // tool will print easier to read. e.g. `libfoo.rlib` instead of
-            // `/home/user/rust/project/target/$T/debug/libfoo.rlib`.
-            Tool::Objdump | Tool::Nm | Tool::Readobj | Tool::Size => {
-                lltool
-                    .current_dir(file.parent().unwrap())
-                    .arg(file.file_name().unwrap());
-            }
-            Tool::Objcopy | Tool::Profdata | Tool::Strip => {
-                lltool.arg(file);
+#[cfg(not(target_os = "windows"))]
+fn exe(name: &str) -> Cow<'_, str> {
+    name.into()
+}
+
+/// Computes which of the `required-features` of the `kind` target `name` are not enabled by the
+/// feature flags passed by the user. Errors if some of these features are not declared by the
+/// package at all
+fn missing_required_features(
+    matches: &clap::ArgMatches,
+    kind: &str,
+    name: &str,
+    features: &[&str],
+) -> Result<Vec<String>, failure::Error> {
+    let metadata = metadata_command().no_deps().exec()?;
+
+    let found = metadata
+        .packages
+        .iter()
+        .filter(|package| metadata.workspace_members.contains(&package.id))
+        .find_map(|package| {
+            package
+                .targets
+                .iter()
+                .find(|target| target.name == name && target.kind.iter().any(|k| k == kind))
+                .map(|target| (package, target))
+        });
+
+    let (package, target) = match found {
+        Some(found) => found,
+        // let `cargo build` report the missing target
+        None => return Ok(vec![]),
+    };
+
+    // features enabled by the flags, including the ones they transitively enable
+    let mut enabled = vec![];
+    let mut pending = features.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+    if !matches.is_present("no-default-features") && package.features.contains_key("default") {
+        pending.push("default".to_string());
+    }
+    while let Some(feature) = pending.pop() {
+        if !enabled.contains(&feature) {
+            if let Some(implied) = package.features.get(&feature) {
+                pending.extend(
+                    implied
+                        .iter()
+                        // skip the features of dependencies, these can't be required
+                        .filter(|f| !f.contains('/') && !f.contains(':'))
+                        .cloned(),
+                );
             }
+            enabled.push(feature);
         }
     }
 
-    // User flags
-    lltool.args(&tool_args);
-
-    if verbose {
-        eprintln!("{:?}", lltool);
+    let missing = target
+        .required_features
+        .iter()
+        .filter(|f| !enabled.contains(f))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let undeclared = missing
+        .iter()
+        .filter(|f| !package.features.contains_key(*f))
+        .map(|f| format!("`{}`", f))
+        .collect::<Vec<_>>();
+    if !undeclared.is_empty() {
+        bail!(
+            "the {} `{}` requires the features {} but package `{}` doesn't declare them",
+            kind,
+            name,
+            undeclared.join(", "),
+            package.name
+        );
     }
 
-    let stdout = io::stdout();
-    let mut stdout = stdout.lock();
-
-    let output = lltool.stderr(Stdio::inherit()).output()?;
+    Ok(missing)
+}
 
-    // post process output
-    let pp_output = match tool {
-        Tool::Objdump | Tool::Nm | Tool::Readobj => postprocess::demangle(&output.stdout),
-        Tool::Size => postprocess::size(&output.stdout),
-        Tool::Objcopy | Tool::Profdata | Tool::Strip => output.stdout.into(),
+/// Prints the `cargo build` and tool invocations of `run` without running them. As nothing is
+/// built, the path of the artifact follows Cargo's conventions and may be off, e.g. when the target
+/// selection is ambiguous
+fn preview(
+    tool: Tool,
+    matches: &clap::ArgMatches,
+    out: &mut dyn Write,
+) -> Result<i32, failure::Error> {
+    let target_flag = target_flag(matches)?;
+    let config = Config::get(&project_dir()?)?;
+    let target = target_flag.as_deref().or_else(|| config.build_target());
+
+    let build = match tool.build_requirement() {
+        BuildRequirement::Never => false,
+        BuildRequirement::Always => true,
+        BuildRequirement::Optional => SELECTORS
+            .iter()
+            .any(|selector| matches.is_present(selector)),
     };
 
-    stdout.write_all(&*pp_output)?;
+    let file = if build {
+        let (cargo, name) = cargo_build(matches, target_flag.as_deref(), false)?;
+        writeln!(out, "build: {}", util::command_line(&cargo))?;
 
-    if output.status.success() {
-        Ok(0)
+        let file = conventional_artifact(matches, name, target)?;
+        writeln!(out, "artifact (approximate): {}", file.display())?;
+
+        Some(file)
     } else {
-        Ok(output.status.code().unwrap_or(1))
-    }
-}
+        None
+    };
+
+    let host;
+    let target = match target {
+        Some(target) => target,
+        None => {
+            host = rustc_version::version_meta()?.host;
+            &host
+        }
+    };
+    if tool.is_builtin() {
+        writeln!(out, "tool: (built-in)")?;
+        return Ok(0);
+    }
+
+    let ctxt = Context::from_target_name(target)?;
+    let tool_args = tool_args(tool, matches)?;
+    let tool_args = tool_args.iter().map(|arg| &**arg).collect::<Vec<_>>();
+    let lltool = tool_invocation(tool, matches, &ctxt, file, &tool_args)?;
+    writeln!(out, "tool: {}", util::command_line(&lltool))?;
+
+    Ok(0)
+}
+
+/// The path where Cargo places the artifact selected by the flags, named `name` (empty if none or
+/// `--lib`), when building for `target`, going by Cargo's conventions rather than asking Cargo.
+/// Without a `name`, it's that of the package, which a virtual workspace lacks
+fn conventional_artifact(
+    matches: &clap::ArgMatches,
+    name: &str,
+    target: Option<&str>,
+) -> Result<PathBuf, failure::Error> {
+    let mut dir = target_dir(matches.value_of("target-dir"))?;
+    if let Some(target) = target {
+        // custom targets are placed in a directory named after the specification
+        dir.push(Path::new(target).file_stem().unwrap());
+    }
+    dir.push(profile_dir(matches));
+
+    // the package selected with `--package` or else that of the project, whose manifest is the
+    // first one found from the project directory up; the target directory may be elsewhere
+    let project = project_dir()?;
+    let package = matches.value_of("package").map(str::to_owned);
+    let package = package.or_else(|| {
+        let manifest = match manifest_path() {
+            Some(path) => path,
+            None => project
+                .ancestors()
+                .map(|dir| dir.join("Cargo.toml"))
+                .find(|manifest| manifest.exists())?,
+        };
+        let manifest = toml::from_str::<toml::Value>(&fs::read_to_string(manifest).ok()?).ok()?;
+        Some(manifest.get("package")?.get("name")?.as_str()?.to_owned())
+    });
+    let name = match (name, &package) {
+        ("", Some(package)) => package,
+        ("", None) => bail!(
+            "couldn't tell which package to inspect (is this a virtual workspace?); pass \
+             `--package <NAME>` or select the artifact with `--bin`, `--example`, etc."
+        ),
+        (name, _) => name,
+    };
+
+    let file = if matches.is_present("lib") {
+        dir.join(format!("lib{}.rlib", name.replace('-', "_")))
+    } else if matches.is_present("example") {
+        dir.join("examples").join(&*exe(name))
+    } else if matches.is_present("test") || matches.is_present("bench") {
+        dir.join("deps")
+            .join(format!("{}-<hash>", name.replace('-', "_")))
+    } else {
+        dir.join(&*exe(name))
+    };
+
+    Ok(file)
+}
+
+// `--print-rustc-version`
+fn print_rustc_version(out: &mut dyn Write) -> Result<(), failure::Error> {
+    let meta = rustc_version::version_meta()?;
+    writeln!(out, "version: {}", meta.semver)?;
+    writeln!(
+        out,
+        "commit-hash: {}",
+        meta.commit_hash.as_deref().unwrap_or("unknown")
+    )?;
+    writeln!(
+        out,
+        "commit-date: {}",
+        meta.commit_date.as_deref().unwrap_or("unknown")
+    )?;
+    writeln!(out, "host: {}", meta.host)?;
+
+    // `rustc_version` doesn't parse this one
+    let output = util::output(rustc().arg("-vV"))?;
+    let llvm = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("LLVM version: ").map(str::to_owned));
+    writeln!(
+        out,
+        "LLVM version: {}",
+        llvm.as_deref().unwrap_or("unknown")
+    )?;
+
+    Ok(())
+}
+
+// `--print-config`
+fn print_config(out: &mut dyn Write) -> Result<(), failure::Error> {
+    let config = Config::get(&project_dir()?)?;
+    let file = config.path.as_ref().map(|path| path.display().to_string());
+    writeln!(out, "file: {}", file.as_deref().unwrap_or("(none)"))?;
+    writeln!(
+        out,
+        "build.target: {}",
+        config.build_target().unwrap_or("(not set)")
+    )?;
+    let target_dir = config.target_dir().map(|dir| dir.display().to_string());
+    writeln!(
+        out,
+        "build.target-dir: {}",
+        target_dir.as_deref().unwrap_or("(not set)")
+    )?;
+    for triple in config.target.keys() {
+        if let Some(runner) = config.runner(triple) {
+            writeln!(out, "target.{}.runner: {}", triple, runner)?;
+        }
+    }
+
+    Ok(())
+}
+
+// `--list-tools`
+fn list_tools(target_flag: Option<&str>, out: &mut dyn Write) -> Result<(), failure::Error> {
+    let ctxt = project_context(target_flag)?;
+    writeln!(out, "host: {}", rustc_version::version_meta()?.host)?;
+    writeln!(out, "target: {}", ctxt.target)?;
+    writeln!(out, "sysroot: {}", sysroot()?.display())?;
+    let bindir = bindir()?;
+    writeln!(out, "bindir: {}", bindir.display())?;
+
+    for tool in Tool::all() {
+        if tool.is_builtin() {
+            writeln!(out, "{}: (built-in)", tool.name())?;
+            continue;
+        }
+
+        let path = bindir.join(&*exe(&format!("llvm-{}", tool.name())));
+        let status = if path.exists() {
+            // e.g. `  LLVM version 19.1.7-rust-1.86.0-stable`, after a line that names LLVM
+            let output = util::output(Command::new(&path).arg("--version"))?;
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .find(|line| line.contains("version"))
+                .map_or_else(
+                    || "unknown version".to_owned(),
+                    |line| line.trim().to_owned(),
+                )
+        } else {
+            "(missing)".to_owned()
+        };
+        writeln!(out, "{}: {} {}", tool.name(), path.display(), status)?;
+    }
+
+    Ok(())
+}
+
+/// The flags that select the artifact to inspect
+const SELECTORS: [&str; 5] = ["bin", "example", "lib", "test", "bench"];
+
+/// The `cargo build` invocation that builds the artifacts selected by the flags, along with the
+/// name of the selected target (empty if none or `--lib`). The `required-features` of the selected
+/// target are only looked up, with `cargo metadata`, if `resolve_features` is set
+fn cargo_build<'a>(
+    matches: &'a clap::ArgMatches,
+    target_flag: Option<&str>,
+    resolve_features: bool,
+) -> Result<(Command, &'a str), failure::Error> {
+    let bin = matches.is_present("bin");
+    let example = matches.is_present("example");
+    let lib = matches.is_present("lib");
+    let test = matches.is_present("test");
+    let bench = matches.is_present("bench");
+
+    // like `cargo metadata`, use the `cargo` that invoked us, if any
+    let mut cargo = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    if let Some(crate_type) = matches.value_of("crate-type") {
+        // `cargo build` can't override the crate type, `cargo rustc` can
+        cargo.args(["rustc", "--crate-type", crate_type]);
+    } else {
+        cargo.arg("build");
+    }
+
+    if let Some(path) = manifest_path() {
+        cargo.arg("--manifest-path").arg(path);
+    }
+
+    if let Some(package) = matches.value_of("package") {
+        cargo.args(["--package", package]);
+    }
+
+    // NOTE we do *not* use `project.target()` here because Cargo will figure things out on
+    // its own (i.e. it will search and parse .cargo/config, etc.)
+    if let Some(target) = target_flag {
+        cargo.args(["--target", target]);
+    }
+
+    // `--max` is a shortcut for `--all-features --release`
+    let max = matches.is_present("max");
+
+    if matches.is_present("all-features") || max {
+        cargo.arg("--all-features");
+    } else {
+        let mut features = matches
+            .value_of("features")
+            .map(|features| {
+                features
+                    .split([' ', ','])
+                    .filter(|f| !f.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        // like `cargo run`, enable the `required-features` of the selected binary or example,
+        // which are otherwise silently skipped by `cargo build`
+        let missing = if !resolve_features {
+            vec![]
+        } else if bin {
+            missing_required_features(matches, "bin", matches.value_of("bin").unwrap(), &features)?
+        } else if example {
+            let name = matches.value_of("example").unwrap();
+            missing_required_features(matches, "example", name, &features)?
+        } else {
+            vec![]
+        };
+        features.extend(missing.iter().map(|f| &**f));
+
+        if !features.is_empty() {
+            cargo.args(["--features", &features.join(" ")]);
+        }
+    }
+
+    if matches.is_present("no-default-features") {
+        cargo.arg("--no-default-features");
+    }
+
+    if matches.is_present("all-targets") {
+        cargo.args(["--bins", "--examples"]);
+    }
+
+    let artifact_name = if bin {
+        let bin_name = matches.value_of("bin").unwrap();
+        cargo.args(["--bin", bin_name]);
+        bin_name
+    } else if example {
+        let example_name = matches.value_of("example").unwrap();
+        cargo.args(["--example", example_name]);
+        example_name
+    } else if lib {
+        cargo.arg("--lib");
+        ""
+    } else if test {
+        let test_name = matches.value_of("test").unwrap();
+        cargo.args(["--test", test_name]);
+        test_name
+    } else if bench {
+        let bench_name = matches.value_of("bench").unwrap();
+        cargo.args(["--bench", bench_name]);
+        bench_name
+    } else {
+        ""
+    };
+
+    if let Some(profile) = matches.value_of("profile") {
+        cargo.args(["--profile", profile]);
+    } else if matches.is_present("release") || max {
+        cargo.arg("--release");
+    }
+
+    if let Some(target_dir) = matches.value_of("target-dir") {
+        cargo.args(["--target-dir", target_dir]);
+    }
+
+    if let Some(jobs) = matches.value_of("jobs") {
+        cargo.args(["--jobs", jobs]);
+    }
+
+    for flag in &["offline", "locked", "frozen"] {
+        if matches.is_present(flag) {
+            cargo.arg(format!("--{}", flag));
+        }
+    }
+
+    // `--verbose` wins: the command line is printed and so is the output of the build
+    if matches.is_present("quiet") && !matches.is_present("verbose") {
+        cargo.arg("--quiet");
+    }
+
+    // NOTE this is unstable: it needs a nightly toolchain and `--target`, which Cargo checks
+    if matches.is_present("build-std") {
+        match matches.value_of("build-std") {
+            Some(crates) => cargo.arg(format!("-Zbuild-std={}", crates)),
+            None => cargo.arg("-Zbuild-std"),
+        };
+    }
+
+    // keep the colors of the output of a suppressed build, should it be printed
+    let color = match matches.value_of("color").unwrap() {
+        "auto" if matches.is_present("suppress-build-output") && io::stderr().is_terminal() => {
+            "always"
+        }
+        color => color,
+    };
+    cargo.args(["--color", color]);
+
+    // Cargo renders the diagnostics, with colors, and only the artifacts end up on stdout
+    cargo.arg("--message-format=json-render-diagnostics");
+
+    Ok((cargo, artifact_name))
+}
+
+/// Builds the project and returns the artifacts that match the selection flags (`--bin`, etc.);
+/// without any of these flags all the executables are returned
+fn build(matches: &clap::ArgMatches) -> Result<Vec<Artifact>, failure::Error> {
+    let verbose = matches.is_present("verbose");
+    let target_flag = target_flag(matches)?;
+    let target_flag = target_flag.as_deref();
+
+    let lib = matches.is_present("lib");
+    let test = matches.is_present("test");
+    let bench = matches.is_present("bench");
+    let std_crate = matches.value_of("std-crate");
+
+    if SELECTORS
+        .iter()
+        .filter(|selector| matches.is_present(selector))
+        .count()
+        > 1
+    {
+        bail!("Only one of `--bin`, `--example`, `--lib`, `--test` or `--bench` must be specified")
+    }
+
+    // the workspace member selected with `--package`
+    let package_id = if let Some(name) = matches.value_of("package") {
+        let metadata = metadata_command().no_deps().exec()?;
+        match metadata.packages.iter().find(|package| {
+            package.name == name && metadata.workspace_members.contains(&package.id)
+        }) {
+            Some(package) => Some(package.id.clone()),
+            None => bail!("package `{}` is not a member of the workspace", name),
+        }
+    } else {
+        None
+    };
+
+    // held until the artifacts have been collected
+    let timeout = matches
+        .value_of("lock-timeout")
+        .map(|secs| Duration::from_secs(secs.parse().unwrap()));
+    let _lock = lock(&target_dir(matches.value_of("target-dir"))?, timeout)?;
+
+    let (mut cargo, artifact_name) = cargo_build(matches, target_flag, true)?;
+    cargo.stdout(Stdio::piped());
+
+    // the artifacts of the last build with the same flags, see `--no-build-if-fresh`
+    let cache = if matches.is_present("no-build-if-fresh") {
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", cargo).hash(&mut hasher);
+        env::current_dir()?.hash(&mut hasher);
+
+        let cache = target_dir(matches.value_of("target-dir"))?
+            .join("cargo-binutils")
+            .join(format!("{:016x}.json", hasher.finish()));
+        if let Some(artifacts) = fresh_artifacts(&cache, matches.value_of("crate-type")) {
+            if verbose {
+                eprintln!(
+                    "reusing the artifacts of the last build ({})",
+                    cache.display()
+                );
+            }
+            return Ok(artifacts);
+        }
+
+        Some(cache)
+    } else {
+        None
+    };
+
+    if verbose {
+        eprintln!("{}", util::command_line(&cargo));
+    }
+
+    let suppress = matches.is_present("suppress-build-output");
+    if suppress {
+        cargo.stderr(Stdio::piped());
+    }
+
+    let mut child = util::spawn(&mut cargo)?;
+    let stdout = child.stdout.take().expect("Pipe to cargo process failed");
+
+    // the output is collected in the background so that Cargo doesn't block on a full pipe while
+    // we read the artifacts
+    let stderr = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut buffer = vec![];
+            let _ = io::Read::read_to_end(&mut stderr, &mut buffer);
+            buffer
+        })
+    });
+
+    // the library of the selected or the current package; dependencies produce library artifacts
+    // too
+    let lib_id = if !lib {
+        None
+    } else if package_id.is_some() {
+        package_id.clone()
+    } else {
+        let metadata = metadata_command().no_deps().exec()?;
+        let dir = project_dir()?;
+        metadata
+            .packages
+            .iter()
+            .filter(|package| dir.starts_with(package.manifest_path.parent().unwrap()))
+            .max_by_key(|package| package.manifest_path.components().count())
+            .map(|package| package.id.clone())
+    };
+
+    let mut artifacts = vec![];
+    for message in parse_messages(stdout) {
+        match message? {
+            Message::CompilerArtifact(artifact)
+                if package_id
+                    .as_ref()
+                    .is_none_or(|id| artifact.package_id == *id)
+                    && (if let Some(name) = std_crate {
+                        // the crates of the standard library are built like dependencies
+                        artifact.target.name == name
+                            && artifact.target.kind.iter().any(|kind| is_lib(kind))
+                    } else if lib {
+                        Some(&artifact.package_id) == lib_id.as_ref()
+                            && artifact.target.kind.iter().any(|kind| is_lib(kind))
+                    } else if test || bench {
+                        // the test harness of a target, which is placed in `deps` with a hash in
+                        // its name, shares the name of the target with e.g. the binary of the
+                        // same name
+                        let kind = if test { "test" } else { "bench" };
+                        artifact.target.name == artifact_name
+                            && artifact.target.kind.iter().any(|k| k == kind)
+                            && artifact.executable.is_some()
+                    } else {
+                        artifact.target.name == artifact_name
+                            || artifact_name.is_empty() && artifact.executable.is_some()
+                    }) =>
+            {
+                artifacts.push(artifact);
+            }
+            _ => (),
+        }
+    }
+
+    let status = child.wait()?;
+    if let Some(stderr) = stderr {
+        let stderr = stderr.join().unwrap_or_default();
+        if !status.success() {
+            io::stderr().write_all(&stderr)?;
+        }
+    }
+    if !status.success() {
+        return Err(BuildFailed {
+            code: util::exit_code(status),
+        }
+        .into());
+    }
+
+    if let Some(cache) = cache {
+        // best effort: failing to save the artifacts only means the next invocation builds
+        if let Some(dir) = cache.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(&cache, serde_json::to_vec(&artifacts)?);
+    }
+
+    Ok(artifacts)
+}
+
+/// Locks `target_dir` so that concurrent invocations build one after the other, like Cargo does;
+/// otherwise one could pick the artifacts that another one is rebuilding with different flags. The
+/// lock is released when the returned file is dropped. There's no lock, only a warning, if the file
+/// system doesn't support locking
+fn lock(target_dir: &Path, timeout: Option<Duration>) -> Result<Option<File>, failure::Error> {
+    let path = target_dir.join("cargo-binutils").join(".lock");
+    let file = fs::create_dir_all(path.parent().unwrap()).and_then(|_| {
+        fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+    });
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!(
+                "warning: couldn't create `{}` ({}); building without locking the target \
+                 directory",
+                path.display(),
+                e
+            );
+            return Ok(None);
+        }
+    };
+
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(Some(file)),
+            Err(TryLockError::WouldBlock) => {
+                if !waiting {
+                    eprintln!("Blocking waiting for file lock on `{}`", path.display());
+                    waiting = true;
+                }
+
+                if let Some(timeout) = timeout {
+                    if start.elapsed() >= timeout {
+                        bail!(
+                            "timed out after {}s waiting for the lock on `{}`",
+                            timeout.as_secs(),
+                            path.display()
+                        );
+                    }
+                }
+
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(TryLockError::Error(e)) => {
+                eprintln!(
+                    "warning: couldn't lock `{}` ({}); building without locking the target \
+                     directory",
+                    path.display(),
+                    e
+                );
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// The artifacts saved in `cache`, if they are all still up to date with their sources. Returns
+/// `None` when that can't be determined
+fn fresh_artifacts(cache: &Path, crate_type: Option<&str>) -> Option<Vec<Artifact>> {
+    let artifacts: Vec<Artifact> = serde_json::from_slice(&fs::read(cache).ok()?).ok()?;
+
+    for artifact in &artifacts {
+        let file = artifact_file(artifact, crate_type);
+        if !file.exists() || newer_source(artifact, file).ok()?.is_some() {
+            return None;
+        }
+    }
+
+    Some(artifacts)
+}
+
+fn determine_artifact(matches: &clap::ArgMatches) -> Result<Option<Artifact>, failure::Error> {
+    let mut artifacts = build(matches)?;
+
+    if artifacts.len() > 1 {
+        // when no artifact was selected, fall back to the binary `cargo run` would run: the
+        // `default-run` of its package or else the binary named after its package, which is what
+        // simple projects have
+        let metadata = metadata_command().no_deps().exec()?;
+        let default_bins = metadata
+            .packages
+            .iter()
+            .map(|package| {
+                let name =
+                    default_run(&package.manifest_path).unwrap_or_else(|| package.name.clone());
+                (&package.id, name)
+            })
+            .collect::<Vec<_>>();
+        let runs_by_default = |artifact: &Artifact| {
+            artifact.target.kind.iter().any(|kind| kind == "bin")
+                && default_bins
+                    .iter()
+                    .any(|(id, name)| **id == artifact.package_id && *name == artifact.target.name)
+        };
+
+        if artifacts.iter().filter(|a| runs_by_default(a)).count() != 1 {
+            // in the root of a virtual workspace `cargo build` builds all the members so the
+            // selection is most likely missing a `--package`
+            let manifest = metadata.workspace_root.join("Cargo.toml");
+            let virtual_manifest = !metadata
+                .packages
+                .iter()
+                .any(|package| package.manifest_path == manifest);
+            if virtual_manifest && !matches.is_present("package") {
+                let mut members = metadata
+                    .packages
+                    .iter()
+                    .filter(|package| metadata.workspace_members.contains(&package.id))
+                    .map(|package| format!("`{}`", package.name))
+                    .collect::<Vec<_>>();
+                members.sort();
+
+                bail!(
+                    "`{}` is a virtual manifest and its members have several executables; pass \
+                     `-p <PACKAGE>` and/or `--bin <NAME>` to select one. Members: {}",
+                    manifest.display(),
+                    members.join(", ")
+                );
+            }
+
+            let bins = artifacts
+                .iter()
+                .filter(|artifact| artifact.target.kind.iter().any(|kind| kind == "bin"))
+                .map(|artifact| format!("`{}`", artifact.target.name))
+                .collect::<Vec<_>>();
+            if bins.len() == artifacts.len() {
+                bail!(
+                    "several binaries could be inspected; pass `--bin <NAME>` with one of {}, or \
+                     set `default-run` in `Cargo.toml`",
+                    bins.join(", ")
+                );
+            }
+
+            bail!("Can only have one matching artifact but found several");
+        }
+
+        artifacts.retain(runs_by_default);
+    }
+
+    match artifacts.pop() {
+        Some(artifact) => Ok(Some(artifact)),
+        None => {
+            let selection = SELECTORS
+                .iter()
+                .find(|selector| matches.is_present(selector))
+                .map(|selector| match matches.value_of(selector) {
+                    Some(name) => format!("`--{} {}`", selector, name),
+                    None => format!("`--{}`", selector),
+                });
+            match selection {
+                Some(selection) => {
+                    bail!("`cargo build` reported no artifact matching {}", selection)
+                }
+                None => bail!("`cargo build` reported no executable artifact"),
+            }
+        }
+    }
+}
+
+/// The `package.default-run` value of the manifest at `path`
+fn default_run(path: &Path) -> Option<String> {
+    let manifest = fs::read_to_string(path).ok()?.parse::<toml::Value>().ok()?;
+    manifest
+        .get("package")?
+        .get("default-run")?
+        .as_str()
+        .map(str::to_owned)
+}
+
+/// The file that holds the debug info of `artifact` when it was built with
+/// `split-debuginfo=packed`: a `.dwp` file or, on macOS, a `.dSYM` bundle
+fn split_debuginfo(artifact: &Artifact) -> Option<&Path> {
+    artifact
+        .filenames
+        .iter()
+        .find(|file| match file.extension() {
+            Some(ext) => ext == "dwp" || ext == "dSYM",
+            None => false,
+        })
+        .map(|file| &**file)
+}
+
+/// Returns the newest source file of the package of `artifact` (everything under `src` and under
+/// the directory of the root source file of the target, which a custom `path` may place elsewhere,
+/// plus `build.rs` and `Cargo.toml`) if it was modified after `file`
+fn newer_source(artifact: &Artifact, file: &Path) -> Result<Option<PathBuf>, failure::Error> {
+    let metadata = metadata_command().no_deps().exec()?;
+    let package = match metadata
+        .packages
+        .iter()
+        .find(|package| package.id == artifact.package_id)
+    {
+        Some(package) => package,
+        None => return Ok(None),
+    };
+    let root = package.manifest_path.parent().unwrap();
+
+    let built = fs::metadata(file)?.modified()?;
+
+    let mut dirs = vec![root.join("src")];
+    let src_path = &artifact.target.src_path;
+    if let Some(dir) = src_path.parent() {
+        if dir == root {
+            // the root of the package also holds e.g. the target directory
+            dirs.push(src_path.clone());
+        } else if !dir.starts_with(&dirs[0]) {
+            dirs.push(dir.to_owned());
+        }
+    }
+
+    let mut newest = None;
+    let sources = dirs
+        .iter()
+        .flat_map(WalkDir::new)
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .chain(vec![root.join("build.rs"), root.join("Cargo.toml")]);
+    for source in sources {
+        if let Ok(modified) = fs::metadata(&source).and_then(|metadata| metadata.modified()) {
+            if modified > built && newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                newest = Some((modified, source));
+            }
+        }
+    }
+
+    Ok(newest.map(|(_, source)| source))
+}
+
+/// Extracts `member` from the `archive` into the target directory and returns its path; the LLVM
+/// tools can only inspect all the members of an archive at once
+fn extract_member(
+    archive: &Path,
+    member: &str,
+    target_dir: &Path,
+    verbose: bool,
+) -> Result<PathBuf, failure::Error> {
+    match archive.extension().and_then(|ext| ext.to_str()) {
+        Some("rlib") | Some("a") | Some("lib") => {}
+        _ => bail!(
+            "`--member` requires an archive (`.rlib` or `.a`) but `{}` isn't one",
+            archive.display()
+        ),
+    }
+
+    let dir = target_dir.join("cargo-binutils").join("members");
+    fs::create_dir_all(&dir)?;
+
+    // `llvm-ar x` extracts into the current directory
+    let mut ar = Command::new("rust-ar");
+    ar.current_dir(&dir).arg("x").arg(archive).arg(member);
+    if verbose {
+        eprintln!("{}", util::command_line(&ar));
+    }
+
+    if !util::status(&mut ar)?.success() {
+        bail!(
+            "couldn't extract `{}` from `{}`; `rust-ar t {}` lists its members",
+            member,
+            archive.display(),
+            archive.display()
+        );
+    }
+
+    Ok(dir.join(member))
+}
+
+/// The commands of `llvm-profdata`
+const PROFDATA_COMMANDS: [&str; 4] = ["merge", "show", "overlap", "order"];
+
+// `llvm-profdata -help` and friends, which take no command
+fn is_help(arg: &str) -> bool {
+    [
+        "-h",
+        "-help",
+        "--help",
+        "-help-hidden",
+        "--help-hidden",
+        "-version",
+        "--version",
+    ]
+    .contains(&arg)
+}
+
+/// Replaces each `--input-list <FILE>` (or `--input-list=<FILE>`) in `args` with the paths listed
+/// in FILE, one per line. Blank lines are ignored
+fn expand_input_lists(args: &[&str]) -> Result<Vec<String>, failure::Error> {
+    let mut expanded = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let list = if *arg == "--input-list" {
+            match args.next() {
+                Some(list) => list,
+                None => bail!("`--input-list` requires a file name"),
+            }
+        } else if let Some(list) = arg.strip_prefix("--input-list=") {
+            list
+        } else {
+            expanded.push(arg.to_string());
+            continue;
+        };
+
+        let contents = fs::read_to_string(list)
+            .map_err(|e| format_err!("couldn't read the input list `{}`: {}", list, e))?;
+        expanded.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+    }
+
+    Ok(expanded)
+}
+
+/// Whether `tool`, invoked with `args`, makes use of the DWARF debug info of the artifact
+fn reads_debuginfo(tool: Tool, args: &[&str]) -> bool {
+    match tool {
+        Tool::Objdump => args.iter().any(|arg| {
+            [
+                "-l",
+                "-S",
+                "--line-numbers",
+                "--source",
+                "--dwarf",
+                "-dwarf",
+            ]
+            .iter()
+            .any(|flag| arg == flag || arg.starts_with(&format!("{}=", flag)))
+        }),
+        Tool::Nm => args
+            .iter()
+            .any(|arg| *arg == "-l" || *arg == "--line-numbers"),
+        Tool::Dwarfdump => true,
+        Tool::Ar
+        | Tool::Cov
+        | Tool::Cxxfilt
+        | Tool::Objcopy
+        | Tool::Profdata
+        | Tool::Readobj
+        | Tool::Size
+        | Tool::Strings
+        | Tool::Strip => false,
+    }
+}
+
+/// Whether `kind` is one of the crate types of a library
+fn is_lib(kind: &str) -> bool {
+    ["lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"].contains(&kind)
+}
+
+/// The file of `artifact` that is passed to the tool. `crate_type` is the crate type the artifact
+/// was built as, if it was overridden with `--crate-type`
+fn artifact_file<'a>(artifact: &'a Artifact, crate_type: Option<&str>) -> &'a Path {
+    let extensions: &[&str] = match crate_type {
+        Some("rlib") | Some("lib") => &["rlib"],
+        Some("dylib") | Some("cdylib") => &["so", "dylib", "dll", "wasm"],
+        Some("staticlib") => &["a", "lib"],
+        // the `executable` of Emscripten binaries is a JavaScript loader; the code is in the
+        // `.wasm` file next to it
+        _ => &["wasm"],
+    };
+
+    if let Some(file) = artifact.filenames.iter().find(|file| {
+        file.extension()
+            .map(|ext| extensions.iter().any(|e| ext == *e))
+            .unwrap_or(false)
+    }) {
+        return file;
+    }
+
+    match &artifact.executable {
+        // Example and bins have an executable
+        Some(val) => val,
+        // Libs have an rlib and an rmeta. We want the rlib, which always
+        // comes first in the filenames array after some quick testing.
+        //
+        // We could instead look for files ending in .rlib, but that would
+        // fail for cdylib and other fancy crate kinds.
+        None => &artifact.filenames[0],
+    }
+}
+
+fn message_format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("message-format")
+        .long("message-format")
+        .takes_value(true)
+        .value_name("FMT")
+        .possible_values(&["human", "json", "csv"])
+        .default_value("human")
+        .help("Output format")
+}
+
+fn validate_seconds(s: String) -> Result<(), String> {
+    s.parse::<u64>()
+        .map(drop)
+        .map_err(|_| format!("`{}` is not a number of seconds", s))
+}
+
+fn validate_jobs(s: String) -> Result<(), String> {
+    match s.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("`{}` is not a positive number of jobs", s)),
+    }
+}
+
+fn validate_size(s: String) -> Result<(), String> {
+    memory::parse_number(&s)
+        .map(drop)
+        .ok_or_else(|| format!("`{}` is not a valid size", s))
+}
+
+fn member_arg() -> Arg<'static, 'static> {
+    Arg::with_name("member")
+        .long("member")
+        .takes_value(true)
+        .value_name("NAME")
+        .help("Inspect only the member NAME (e.g. `foo.o`) of the archive (`.rlib` or `.a`)")
+}
+
+fn output_arg() -> Arg<'static, 'static> {
+    Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .takes_value(true)
+        .value_name("FILE")
+        .help("Write the result to FILE")
+}
+
+fn in_place_arg() -> Arg<'static, 'static> {
+    Arg::with_name("in-place")
+        .long("in-place")
+        .conflicts_with("output")
+        .help("Modify the artifact built by Cargo in place")
+}
+
+fn keep_symbol_arg() -> Arg<'static, 'static> {
+    Arg::with_name("keep-symbol")
+        .long("keep-symbol")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .value_name("NAME")
+        .help("Do not remove the symbol NAME (can be used several times)")
+}
+
+// Output formats of `llvm-objcopy -O`: the raw formats plus any BFD style ELF name, e.g.
+// `elf32-littlearm`
+fn validate_output_target(format: String) -> Result<(), String> {
+    if ["binary", "ihex", "srec"].contains(&&*format)
+        || format.starts_with("elf32-")
+        || format.starts_with("elf64-")
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` is not an output format supported by `llvm-objcopy`; expected `binary`, \
+             `ihex`, `srec` or an ELF format like `elf32-littlearm`",
+            format
+        ))
+    }
+}
+
+// The section flags that `llvm-objcopy` understands
+const SECTION_FLAGS: &[&str] = &[
+    "alloc", "load", "noload", "readonly", "exclude", "debug", "code", "data", "rom", "share",
+    "contents", "merge", "strings", "large",
+];
+
+// `OLD=NEW`, optionally followed by flags, e.g. `.text=.boot,alloc,code`
+fn validate_rename_section(s: String) -> Result<(), String> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.is_empty() => {
+            let mut new = new.split(',');
+            if new.next().is_some_and(|name| !name.is_empty()) {
+                return new.try_for_each(validate_section_flag);
+            }
+        }
+        _ => {}
+    }
+
+    Err(format!(
+        "`{}` is not a section renaming; expected `OLD=NEW`, e.g. `.text=.boot`",
+        s
+    ))
+}
+
+// `NAME=FLAGS`, e.g. `.noinit=alloc,noload`
+fn validate_section_flags(s: String) -> Result<(), String> {
+    match s.split_once('=') {
+        Some((name, flags)) if !name.is_empty() && !flags.is_empty() => {
+            flags.split(',').try_for_each(validate_section_flag)
+        }
+        _ => Err(format!(
+            "`{}` is not a section and its flags; expected `NAME=FLAGS`, e.g. `.noinit=alloc,noload`",
+            s
+        )),
+    }
+}
+
+fn validate_section_flag(flag: &str) -> Result<(), String> {
+    if SECTION_FLAGS.contains(&flag) {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` is not a section flag; expected one of {}",
+            flag,
+            SECTION_FLAGS.join(", ")
+        ))
+    }
+}
+
+fn collapse_closures_arg() -> Arg<'static, 'static> {
+    Arg::with_name("collapse-closures")
+        .long("collapse-closures")
+        .help("Render chains of nested closures in demangled symbols compactly")
+}
+
+// C++ symbols are now demangled by default; the flag is kept so existing invocations still work
+fn demangle_c_arg() -> Arg<'static, 'static> {
+    Arg::with_name("demangle-c").long("demangle-c").hidden(true)
+}
+
+fn no_demangle_arg() -> Arg<'static, 'static> {
+    Arg::with_name("no-demangle")
+        .long("no-demangle")
+        .conflicts_with_all(&[
+            "collapse-closures",
+            "demangle-c",
+            "demangle-backend",
+            "demangle-threads",
+        ])
+        .help(
+            "Leave the Rust and C++ symbols mangled (only `nm`, `objdump` and `readobj` demangle)",
+        )
+}
+
+fn demangle_threads_arg() -> Arg<'static, 'static> {
+    Arg::with_name("demangle-threads")
+        .long("demangle-threads")
+        .takes_value(true)
+        .value_name("N")
+        .validator(validate_threads)
+        .help("Demangle the output on N threads (default: 1); speeds up large outputs")
+}
+
+fn validate_threads(s: String) -> Result<(), String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("`{}` is not a positive number of threads", s)),
+    }
+}
+
+fn demangle_backend_arg() -> Arg<'static, 'static> {
+    Arg::with_name("demangle-backend")
+        .long("demangle-backend")
+        .takes_value(true)
+        .value_name("BACKEND")
+        .possible_values(&["rustc", "itanium"])
+        .help(
+            "Demangle Rust symbols with `rustc-demangle` (default) or a generic Itanium demangler",
+        )
+}
+
+/// Handles a leading `+toolchain` argument, e.g. `cargo size +nightly`, and returns the rest of
+/// the arguments
+///
+/// Like rustup's proxies do, the toolchain is selected through `RUSTUP_TOOLCHAIN` so it applies to
+/// every `cargo` and `rustc` we invoke and, through `rustc --print sysroot`, to the LLVM tools.
+/// `CARGO` is dropped as it points to the `cargo` of the toolchain that invoked us
+fn select_toolchain(mut args: Vec<OsString>) -> Vec<OsString> {
+    // `cargo size +nightly` runs `cargo-size size +nightly`, but `cargo-size` may also be run
+    // directly
+    let pos = (1..args.len().min(3)).find(|&i| {
+        args[i]
+            .to_str()
+            .is_some_and(|arg| arg.starts_with('+') && arg.len() > 1)
+    });
+
+    if let Some(pos) = pos {
+        let arg = args.remove(pos);
+        env::set_var("RUSTUP_TOOLCHAIN", &arg.to_str().unwrap()[1..]);
+        env::remove_var("CARGO");
+    }
+
+    args
+}
+
+/// Runs the `cargo-$tool` subcommand with the arguments of the process, printing the output of
+/// the tool, and returns the exit code of the process
+pub fn run(tool: Tool, examples: Option<&str>) -> Result<i32, failure::Error> {
+    let stdout = io::stdout();
+    let terminal = stdout.is_terminal();
+    let args = env::args_os().collect();
+
+    match run_to(tool, examples, args, &mut stdout.lock(), terminal) {
+        // `--help`, `--version` and invalid arguments
+        Err(e) => match e.downcast::<clap::Error>() {
+            Ok(e) => e.exit(),
+            Err(e) => match e.downcast::<BuildFailed>() {
+                Ok(e) => Ok(e.code),
+                Err(e) => Err(e),
+            },
+        },
+        Ok(code) => Ok(code),
+    }
+}
+
+/// Like `run` but returns the output of the tool, post-processed, instead of printing it. `args`
+/// are the arguments that follow `cargo $tool` on the command line, e.g. `["--bin", "app", "--",
+/// "-A"]`. Invalid arguments are reported as errors. Notes, warnings and the output of `cargo
+/// build` still go to stderr
+pub fn run_capture(tool: Tool, args: &[&str]) -> Result<ToolOutput, failure::Error> {
+    let mut args_os = vec![
+        OsString::from(format!("cargo-{}", tool.name())),
+        OsString::from(tool.name()),
+    ];
+    args_os.extend(args.iter().map(OsString::from));
+
+    let mut stdout = vec![];
+    let exit_code = run_to(tool, None, args_os, &mut stdout, false)?;
+
+    Ok(ToolOutput { stdout, exit_code })
+}
+
+// The pipeline shared by `run` and `run_capture`: the output goes to `out`, which is colored if
+// it's a `terminal`
+fn run_to(
+    tool: Tool,
+    examples: Option<&str>,
+    args: Vec<OsString>,
+    out: &mut dyn Write,
+    terminal: bool,
+) -> Result<i32, failure::Error> {
+    let name = tool.name();
+    let build_requirement = tool.build_requirement();
+
+    let app = App::new(format!("cargo-{}", name));
+    let about = if tool.is_builtin() {
+        format!(
+            "Like `llvm-{}`, which the Rust toolchain doesn't ship.",
+            name
+        )
+    } else {
+        format!(
+            "Proxy for the `llvm-{}` tool shipped with the Rust toolchain.",
+            name
+        )
+    };
+    let after_help = format!(
+        "\
+The arguments specified *after* the `--` will be passed to the proxied tool invocation.
+
+To see all the flags the proxied tool accepts run `cargo-{} -- -help`.{}",
+        name,
+        examples.unwrap_or("")
+    );
+    let app = app
+        .about(&*about)
+        .version(env!("CARGO_PKG_VERSION"))
+        .setting(AppSettings::DontCollapseArgsInUsage)
+        // as this is used as a Cargo subcommand the first argument will be the name of the binary
+        // we ignore this argument
+        .arg(Arg::with_name("binary-name").hidden(true))
+        .arg(
+            Arg::with_name("target")
+                .long("target")
+                .takes_value(true)
+                .value_name("TRIPLE")
+                .help("Target triple for which the code is compiled"),
+        )
+        .arg(
+            Arg::with_name("target-file")
+                .long("target-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("File whose first line is the target; an alternative to `--target`"),
+        )
+        .arg(
+            Arg::with_name("auto-target")
+                .long("auto-target")
+                .conflicts_with_all(&["target", "target-file"])
+                .help(
+                    "Use the only target, besides the host, installed with rustup if no target \
+                     is configured",
+                ),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Use verbose output (-vv also prints the configuration in use)"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .help("Coloring of the output of `cargo build` and of the tool"),
+        )
+        // accepted by all the tools so that scripts can pass it uniformly
+        .arg(no_demangle_arg())
+        .arg(
+            Arg::with_name("preview")
+                .long("preview")
+                .help("Print the `cargo build` and tool invocations instead of running them"),
+        )
+        .arg(
+            Arg::with_name("print-rustc-version")
+                .long("print-rustc-version")
+                .help("Print the version of `rustc`, for bug reports, and exit"),
+        )
+        .arg(
+            Arg::with_name("print-config")
+                .long("print-config")
+                .help("Print the Cargo configuration picked up from `.cargo/config` and exit"),
+        )
+        .arg(
+            Arg::with_name("list-tools").long("list-tools").help(
+                "Print where the LLVM tools were looked for, which ones are installed and exit",
+            ),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .long("yes")
+                .help("Install the `llvm-tools-preview` component, if missing, without asking"),
+        )
+        .arg(
+            Arg::with_name("json-events")
+                .long("json-events")
+                .conflicts_with("server")
+                .help(
+                    "Print newline delimited JSON events describing the build, the artifact, the \
+                     tool invocation and its output",
+                ),
+        )
+        .arg(Arg::with_name("server").long("server").help(
+            "(experimental) Read `<tool> <artifact-path> <args...>` requests from stdin \
+                     and run them, reusing the same context",
+        ))
+        // everything after `--` is captured verbatim, even if it looks like one of our own flags
+        .arg(Arg::with_name("args").multiple(true).last(true))
+        .after_help(&*after_help);
+
+    let app = if build_requirement != BuildRequirement::Never {
+        app.arg(
+            Arg::with_name("package")
+                .long("package")
+                .short("p")
+                .takes_value(true)
+                .value_name("SPEC")
+                .help("Package with the target to build"),
+        )
+        .arg(
+            Arg::with_name("bin")
+                .long("bin")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Build only the specified binary"),
+        )
+        .arg(
+            Arg::with_name("example")
+                .long("example")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Build only the specified example"),
+        )
+        .arg(
+            Arg::with_name("test")
+                .long("test")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Build only the specified test target"),
+        )
+        .arg(
+            Arg::with_name("bench")
+                .long("bench")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Build only the specified bench target"),
+        )
+        .arg(
+            Arg::with_name("lib")
+                .long("lib")
+                .help("Build only this package's library"),
+        )
+        .arg(
+            Arg::with_name("crate-type")
+                .long("crate-type")
+                .takes_value(true)
+                .value_name("TYPE")
+                .possible_values(&["bin", "lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"])
+                .help("Build the library (or example) as this crate type, instead of the declared one"),
+        )
+        .arg(
+            Arg::with_name("release")
+                .long("release")
+                .help("Build artifacts in release mode, with optimizations"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .value_name("PROFILE-NAME")
+                .conflicts_with("release")
+                .help("Build artifacts with the specified profile"),
+        )
+        .arg(
+            Arg::with_name("build-std")
+                .long("build-std")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .value_name("CRATES")
+                .help(
+                    "(unstable) Build the standard library, or the comma separated CRATES of it, \
+                     from source; forwarded to `cargo build` as `-Z build-std`",
+                ),
+        )
+        .arg(
+            Arg::with_name("std-crate")
+                .long("std-crate")
+                .takes_value(true)
+                .value_name("NAME")
+                .requires("build-std")
+                .conflicts_with_all(&["package", "bin", "example", "lib", "test", "bench"])
+                .help("Inspect the NAME crate of the standard library, e.g. `core`, built by `--build-std`"),
+        )
+        .arg(
+            Arg::with_name("file")
+                .long("file")
+                .takes_value(true)
+                .value_name("PATH")
+                .conflicts_with_all(&["package", "bin", "example", "lib", "test", "bench", "std-crate", "all-targets"])
+                .help("Inspect the file at PATH, e.g. a binary built elsewhere, instead of building the project"),
+        )
+        .arg(
+            Arg::with_name("all-targets")
+                .long("all-targets")
+                .conflicts_with_all(&["bin", "example", "lib", "test", "bench", "std-crate"])
+                .help("Build all the binaries and examples and inspect each of them (`size`, `nm` and `objdump` only)"),
+        )
+        .arg(
+            Arg::with_name("no-fail-fast")
+                .long("no-fail-fast")
+                .requires("all-targets")
+                .help("With `--all-targets`, keep inspecting the artifacts after the tool fails on one"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .help(
+                    "Do not print the output of `cargo build` (unless `--verbose` is passed) nor \
+                     notes about the inspected artifact",
+                ),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .long("jobs")
+                .short("j")
+                .takes_value(true)
+                .value_name("N")
+                .validator(validate_jobs)
+                .help("Run up to N jobs in parallel in `cargo build`"),
+        )
+        .arg(
+            Arg::with_name("offline")
+                .long("offline")
+                .help("Run `cargo build` without accessing the network"),
+        )
+        .arg(
+            Arg::with_name("locked")
+                .long("locked")
+                .help("Require `Cargo.lock` to be up to date for `cargo build`"),
+        )
+        .arg(
+            Arg::with_name("frozen")
+                .long("frozen")
+                .help("Require `Cargo.lock` and the cache to be up to date for `cargo build`"),
+        )
+        .arg(
+            Arg::with_name("suppress-build-output")
+                .long("suppress-build-output")
+                .help("Hide the output of `cargo build` unless the build fails"),
+        )
+        .arg(
+            Arg::with_name("features")
+                .long("features")
+                .takes_value(true)
+                .value_name("FEATURES")
+                .help("Space-separated list of features to activate"),
+        )
+        .arg(
+            Arg::with_name("no-default-features")
+                .long("no-default-features")
+                .help("Do not activate the `default` feature"),
+        )
+        .arg(
+            Arg::with_name("all-features")
+                .long("all-features")
+                .takes_value(false)
+                .conflicts_with_all(&["features", "no-default-features"])
+                .help("Activate all available features"),
+        )
+        .arg(
+            Arg::with_name("max")
+                .long("max")
+                .conflicts_with_all(&["features", "no-default-features", "profile"])
+                .help(
+                    "Build the largest variant of the artifact: same as `--all-features --release`",
+                ),
+        )
+        .arg(
+            Arg::with_name("manifest-path")
+                .long("manifest-path")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Path to the Cargo.toml of the project to inspect"),
+        )
+        .arg(
+            Arg::with_name("target-dir")
+                .long("target-dir")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Directory for all generated artifacts"),
+        )
+        .arg(
+            Arg::with_name("list-artifacts")
+                .long("list-artifacts")
+                .help("Build the selected artifacts, print them as JSON and exit"),
+        )
+        .arg(
+            Arg::with_name("print-target-dir")
+                .long("print-target-dir")
+                .help("Print the directory where artifacts are placed and exit"),
+        )
+        .arg(
+            Arg::with_name("show-runner")
+                .long("show-runner")
+                .help("Print the runner that `cargo run` uses for the target and exit"),
+        )
+        .arg(
+            Arg::with_name("relative-paths")
+                .long("relative-paths")
+                .help(
+                    "Run `nm`, `objdump`, `readobj` and `size` from the directory of the artifact \
+                     so they print its file name rather than its full path (default)",
+                ),
+        )
+        .arg(
+            Arg::with_name("absolute-paths")
+                .long("absolute-paths")
+                .conflicts_with("relative-paths")
+                .help(
+                    "Pass the full path of the artifact and run the tool from the current \
+                     directory",
+                ),
+        )
+        .arg(
+            Arg::with_name("no-build-if-fresh")
+                .long("no-build-if-fresh")
+                .help(
+                    "Skip `cargo build` if the artifacts of the last build with the same flags \
+                     are newer than their sources",
+                ),
+        )
+        .arg(
+            Arg::with_name("no-build")
+                .long("no-build")
+                .conflicts_with_all(&["file", "all-targets", "test", "bench", "std-crate", "no-build-if-fresh"])
+                .help("Skip `cargo build` and inspect the artifact of the last build, where Cargo places it"),
+        )
+        .arg(
+            Arg::with_name("lock-timeout")
+                .long("lock-timeout")
+                .takes_value(true)
+                .value_name("SECS")
+                .validator(validate_seconds)
+                .help(
+                    "Give up if another invocation has been building in the same target \
+                     directory for SECS seconds (default: wait)",
+                ),
+        )
+        .arg(
+            Arg::with_name("check-stale")
+                .long("check-stale")
+                .help("Warn if the artifact is older than the sources of its package"),
+        )
+        .arg(
+            Arg::with_name("fail-on-stale")
+                .long("fail-on-stale")
+                .requires("check-stale")
+                .help("Fail, instead of warning, if the artifact is stale"),
+        )
+    } else {
+        app
+    };
+
+    // `objcopy` and `strip` have their own `--output`: the file they produce
+    let app = match tool {
+        Tool::Objcopy | Tool::Strip => app,
+        _ => app.arg(output_arg()),
+    };
+
+    let matches = match tool {
+        Tool::Objdump => app
+            .arg(member_arg())
+            .arg(collapse_closures_arg())
+            .arg(demangle_c_arg())
+            .arg(demangle_backend_arg())
+            .arg(demangle_threads_arg())
+            .arg(
+                Arg::with_name("disassemble")
+                    .long("disassemble")
+                    .short("d")
+                    .help("Disassemble the executable sections; shorthand for `-- -d`"),
+            )
+            .arg(
+                Arg::with_name("all")
+                    .long("all")
+                    .requires("disassemble")
+                    .help("With `--disassemble`, disassemble all the sections; shorthand for `-- -D`"),
+            )
+            .arg(
+                Arg::with_name("line-numbers")
+                    .long("line-numbers")
+                    .help(
+                        "Disassemble and annotate the disassembly with the source lines; needs \
+                         debug info",
+                    ),
+            )
+            .arg(
+                Arg::with_name("annotate-symbols")
+                    .long("annotate-symbols")
+                    .help(
+                        "Name the symbols whose addresses appear in the operands of the \
+                         disassembly (runs `nm` on the artifact)",
+                    ),
+            )
+            .arg(
+                Arg::with_name("print-arch-name")
+                    .long("print-arch-name")
+                    .help("Print the architecture name passed to `llvm-objdump` and exit"),
+            )
+            .arg(
+                Arg::with_name("no-arch-name")
+                    .long("no-arch-name")
+                    .help("Do not pass the architecture of the target to the tool"),
+            )
+            .arg(
+                Arg::with_name("mcpu")
+                    .long("mcpu")
+                    .takes_value(true)
+                    .value_name("NAME")
+                    .help("Disassemble the instructions of this CPU, e.g. `cortex-m4` (default: the `-C target-cpu` of the rustflags)"),
+            )
+            .arg(
+                Arg::with_name("mattr")
+                    .long("mattr")
+                    .takes_value(true)
+                    .value_name("FEATURES")
+                    .help("Disassemble the instructions of these target features, e.g. `+fp-armv8` (default: the `-C target-feature`s of the rustflags)"),
+            )
+            .arg(
+                Arg::with_name("start-address")
+                    .long("start-address")
+                    .takes_value(true)
+                    .value_name("ADDR")
+                    .validator(validate_address)
+                    .help("Disassemble only from this address onwards (e.g. 0x8000400)"),
+            )
+            .arg(
+                Arg::with_name("stop-address")
+                    .long("stop-address")
+                    .takes_value(true)
+                    .value_name("ADDR")
+                    .validator(validate_address)
+                    .help("Stop disassembling at this address"),
+            ),
+        Tool::Objcopy => app
+            .arg(keep_symbol_arg())
+            .arg(output_arg())
+            .arg(in_place_arg())
+            .arg(
+                Arg::with_name("output-target")
+                    .long("output-target")
+                    .takes_value(true)
+                    .value_name("FMT")
+                    .validator(validate_output_target)
+                    .conflicts_with_all(&["ihex", "srec"])
+                    .requires("output")
+                    .help("Convert to the FMT object format (binary, ihex, srec or elf*-*)"),
+            )
+            .arg(
+                Arg::with_name("strip-debug")
+                    .long("strip-debug")
+                    .help("Remove the debug info from the output"),
+            )
+            .arg(
+                Arg::with_name("strip-all")
+                    .long("strip-all")
+                    .conflicts_with("strip-debug")
+                    .help("Remove the symbol table and the debug info from the output"),
+            )
+            .arg(
+                Arg::with_name("ihex")
+                    .long("ihex")
+                    .conflicts_with("srec")
+                    .requires("output")
+                    .help("Convert to Intel HEX; shorthand for `--output-target ihex`"),
+            )
+            .arg(
+                Arg::with_name("srec")
+                    .long("srec")
+                    .requires("output")
+                    .help("Convert to Motorola S-records; shorthand for `--output-target srec`"),
+            )
+            .arg(
+                Arg::with_name("rename-section")
+                    .long("rename-section")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("OLD=NEW")
+                    .validator(validate_rename_section)
+                    .help(
+                        "Rename the section OLD to NEW, optionally followed by its new flags, e.g. \
+                         `.text=.boot,alloc,code` (can be used several times)",
+                    ),
+            )
+            .arg(
+                Arg::with_name("set-section-flags")
+                    .long("set-section-flags")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("NAME=FLAGS")
+                    .validator(validate_section_flags)
+                    .help(
+                        "Set the flags of the section NAME, e.g. `.noinit=alloc,noload` (can be \
+                         used several times)",
+                    ),
+            ),
+        Tool::Strip => app
+            .arg(keep_symbol_arg())
+            .arg(output_arg())
+            .arg(in_place_arg())
+            .arg(
+                Arg::with_name("only-keep-debug")
+                    .long("only-keep-debug")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help("Save the debug info of the artifact to FILE before stripping it"),
+            )
+            .arg(
+                Arg::with_name("add-gnu-debuglink")
+                    .long("add-gnu-debuglink")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help("After stripping, link the artifact to the debug info in FILE"),
+            ),
+        Tool::Nm => app
+            .arg(member_arg())
+            .arg(message_format_arg())
+            .arg(collapse_closures_arg())
+            .arg(demangle_c_arg())
+            .arg(demangle_backend_arg())
+            .arg(demangle_threads_arg())
+            .arg(
+                Arg::with_name("color-symbols-by-section")
+                    .long("color-symbols-by-section")
+                    .help(
+                        "Color the symbols by the kind of section they live in: text, data, bss, \
+                         weak or undefined",
+                    ),
+            )
+            .arg(
+                Arg::with_name("numeric-sort")
+                    .long("numeric-sort")
+                    .help("Sort the symbols by address rather than by name"),
+            )
+            .arg(
+                Arg::with_name("no-sort")
+                    .long("no-sort")
+                    .conflicts_with("numeric-sort")
+                    .help("List the symbols in the order they appear in the symbol table"),
+            )
+            .arg(
+                Arg::with_name("size-sort")
+                    .long("size-sort")
+                    .conflicts_with_all(&["numeric-sort", "no-sort"])
+                    .help("Sort the symbols by size, the biggest last"),
+            )
+            .arg(
+                Arg::with_name("undefined-only")
+                    .long("undefined-only")
+                    .help("Only list the symbols that are not defined by the artifact"),
+            ),
+        Tool::Size => app
+            .arg(message_format_arg())
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .value_name("FMT")
+                    .possible_values(&["berkeley", "json"])
+                    .conflicts_with_all(&["message-format", "regions"])
+                    .help(
+                        "Report the totals (`berkeley`, default) or every section with its size and \
+                         address (`json`)",
+                    ),
+            )
+            .arg(member_arg()).arg(
+            Arg::with_name("regions")
+                .long("regions")
+                .takes_value(true)
+                .value_name("LINKER_SCRIPT")
+                .help("Report the utilization of the memory regions declared in LINKER_SCRIPT"),
+        )
+        .arg(
+            Arg::with_name("flash-max")
+                .long("flash-max")
+                .takes_value(true)
+                .value_name("SIZE")
+                .validator(validate_size)
+                .help("Exit with code 2 if `text` + `data` exceeds SIZE (e.g. 256K)"),
+        )
+        .arg(
+            Arg::with_name("ram-max")
+                .long("ram-max")
+                .takes_value(true)
+                .value_name("SIZE")
+                .validator(validate_size)
+                .help("Exit with code 2 if `data` + `bss` exceeds SIZE (e.g. 64K)"),
+        )
+        .arg(
+            Arg::with_name("section")
+                .long("section")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME")
+                .help("Only report the section NAME, and the total of the reported sections (can be used several times)"),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .takes_value(true)
+                .value_name("KEY")
+                .possible_values(&["size", "name", "address"])
+                .help("List the sections sorted by KEY; largest first for `size`"),
+        )
+        .arg(
+            Arg::with_name("human")
+                .long("human")
+                .short("H")
+                .conflicts_with_all(&["message-format", "format"])
+                .help("Print the sizes with binary units, e.g. `1.6KiB`"),
+        ),
+        Tool::Readobj => app
+            .arg(member_arg())
+            .arg(collapse_closures_arg())
+            .arg(demangle_c_arg())
+            .arg(demangle_backend_arg())
+            .arg(demangle_threads_arg()),
+        Tool::Cxxfilt => app
+            .arg(collapse_closures_arg())
+            .arg(demangle_backend_arg()),
+        Tool::Dwarfdump => app.arg(
+            Arg::with_name("dsym")
+                .long("dsym")
+                .help("Inspect the `.dSYM` bundle next to the artifact, which holds its debug info"),
+        ),
+        Tool::Ar | Tool::Cov | Tool::Profdata | Tool::Strings => app,
+    }
+    .get_matches_from_safe(select_toolchain(args))?;
+
+    // `-o` writes what would have gone to `out` to a file instead
+    let mut file;
+    let (out, terminal) = match matches.value_of("output") {
+        Some(path) if !tool.produces_output() => {
+            file =
+                File::create(path).map_err(|e| format_err!("couldn't create `{}`: {}", path, e))?;
+            (&mut file as &mut dyn Write, false)
+        }
+        _ => (out, terminal),
+    };
+
+    if !matches.is_present("json-events") {
+        return run_matches(tool, &matches, out, &mut Events::disabled(), terminal);
+    }
+
+    // the output of the tool is part of the last event
+    let mut output = vec![];
+    let mut events = Events::new(out);
+    let result = run_matches(tool, &matches, &mut output, &mut events, false);
+    events.result(&output, &result)?;
+    result
+}
+
+// The part of `run_to` that follows the parsing of the arguments
+fn run_matches(
+    tool: Tool,
+    matches: &clap::ArgMatches,
+    out: &mut dyn Write,
+    events: &mut Events,
+    terminal: bool,
+) -> Result<i32, failure::Error> {
+    let name = tool.name();
+    let build_requirement = tool.build_requirement();
+
+    // like Cargo, a relative path is relative to the CWD
+    let manifest_path = match matches.value_of("manifest-path") {
+        Some(path) if !Path::new(path).is_file() => bail!("the manifest `{}` doesn't exist", path),
+        Some(path) => Some(env::current_dir()?.join(path)),
+        None => None,
+    };
+    *MANIFEST_PATH.lock().unwrap() = manifest_path;
+
+    let verbose = matches.is_present("verbose");
+    let target_flag = target_flag(matches)?;
+    let target_flag = target_flag.as_deref();
+    let config = Config::get(&project_dir()?)?;
+    if let Some(target) = target_flag.or_else(|| config.build_target()) {
+        check_target(target)?;
+    }
+
+    if matches.is_present("preview") {
+        return preview(tool, matches, out);
+    }
+
+    if matches.is_present("print-rustc-version") {
+        print_rustc_version(out)?;
+        return Ok(0);
+    }
+
+    if matches.is_present("print-config") {
+        print_config(out)?;
+        return Ok(0);
+    }
+
+    if matches.is_present("list-tools") {
+        list_tools(target_flag, out)?;
+        return Ok(0);
+    }
+
+    let target_dir = target_dir(matches.value_of("target-dir"))?;
+
+    if matches.occurrences_of("verbose") > 1 {
+        let config = Config::get(&project_dir()?)?;
+        eprintln!(
+            "build.target: {}",
+            config.build_target().unwrap_or("(not set)")
+        );
+        eprintln!("sysroot: {}", sysroot()?.display());
+        eprintln!("bindir: {}", bindir()?.display());
+        eprintln!("target-dir: {}", target_dir.display());
+        if build_requirement != BuildRequirement::Never {
+            eprintln!("profile-dir: {}", profile_dir(matches));
+        }
+    }
+
+    if matches.is_present("print-target-dir") {
+        writeln!(out, "{}", target_dir.display())?;
+        return Ok(0);
+    }
+
+    if matches.is_present("show-runner") {
+        let ctxt = project_context(target_flag)?;
+        // like Cargo, the environment takes precedence over the configuration
+        let env_var = format!(
+            "CARGO_TARGET_{}_RUNNER",
+            ctxt.target.to_uppercase().replace(['-', '.'], "_")
+        );
+        let runner = match env::var(&env_var) {
+            Ok(runner) => Some(runner),
+            Err(_) => Config::get(&project_dir()?)?.runner(&ctxt.target),
+        };
+        match runner {
+            Some(runner) => writeln!(out, "{}", runner)?,
+            None => eprintln!("no runner is configured for `{}`", ctxt.target),
+        }
+        return Ok(0);
+    }
+
+    if matches.is_present("print-arch-name") {
+        let ctxt = project_context(target_flag)?;
+        writeln!(out, "{}", llvm::arch_name(ctxt.rustc_cfg(), &ctxt.target))?;
+        return Ok(0);
+    }
+
+    if matches.is_present("server") {
+        return server::serve(&project_context(target_flag)?, verbose, out);
+    }
+
+    if matches.is_present("list-artifacts") {
+        let artifacts = build(matches)?
+            .iter()
+            .map(|artifact| {
+                serde_json::json!({
+                    "name": artifact.target.name,
+                    "kind": artifact.target.kind.first(),
+                    "path": artifact_file(artifact, matches.value_of("crate-type")),
+                })
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", serde_json::to_string_pretty(&artifacts)?)?;
+        return Ok(0);
+    }
+
+    ensure_installed(tool, matches.is_present("yes"))?;
+
+    if matches.is_present("all-targets") {
+        if !matches!(tool, Tool::Nm | Tool::Objdump | Tool::Size) {
+            bail!("`--all-targets` is only supported by `size`, `nm` and `objdump`");
+        }
+
+        events.build_start()?;
+        let artifacts = build(matches)?;
+        let count = artifacts.len();
+        let mut code = 0;
+        let mut failures = vec![];
+        for (i, artifact) in artifacts.into_iter().enumerate() {
+            let file = artifact_file(&artifact, matches.value_of("crate-type"));
+            events.artifact(file)?;
+
+            // like `head` does with several files, each output starts with a header
+            if i != 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "==> {} <==", file.display())?;
+            out.flush()?;
+
+            let input = file.to_owned();
+            let result = run_artifact(
+                tool,
+                matches,
+                Some(artifact),
+                Some(input.clone()),
+                &target_dir,
+                target_flag,
+                out,
+                events,
+                terminal,
+            );
+            // like `cargo test`, the first failure stops the loop unless `--no-fail-fast`
+            let (artifact_code, failure) = match result {
+                Ok(0) => continue,
+                Ok(artifact_code) if !matches.is_present("no-fail-fast") => {
+                    return Ok(artifact_code)
+                }
+                Err(e) if !matches.is_present("no-fail-fast") => return Err(e),
+                Ok(artifact_code) => (artifact_code, format!("exit code {}", artifact_code)),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    (1, "error".to_owned())
+                }
+            };
+            // the first failure wins
+            if code == 0 {
+                code = artifact_code;
+            }
+            failures.push((input, failure));
+        }
+
+        if !failures.is_empty() {
+            eprintln!(
+                "error: `{}` failed on {} of {} artifacts:",
+                name,
+                failures.len(),
+                count
+            );
+            for (file, failure) in &failures {
+                eprintln!("    {} ({})", file.display(), failure);
+            }
+        }
+
+        return Ok(code);
+    }
+
+    // Figure out which artifact to use with the tool; `--file` stands in for one and
+    // `--no-build` takes that of the last build
+    let wants_artifact = !matches.is_present("file")
+        && match build_requirement {
+            BuildRequirement::Never => false,
+            BuildRequirement::Always => true,
+            BuildRequirement::Optional => SELECTORS
+                .iter()
+                .any(|selector| matches.is_present(selector)),
+        };
+    let build = wants_artifact && !matches.is_present("no-build");
+
+    // modifying the artifact behind Cargo's back must be asked for explicitly
+    if wants_artifact
+        && (tool == Tool::Objcopy || tool == Tool::Strip)
+        && !matches.is_present("in-place")
+        && !matches.is_present("output")
+    {
+        bail!(
+            "`{}` would modify the artifact built by Cargo; pass `--output <FILE>` to write the \
+             result elsewhere or `--in-place` to modify the artifact",
+            name
+        );
+    }
+
+    let artifact = if build {
+        events.build_start()?;
+        determine_artifact(matches)?
+    } else {
+        None
+    };
+
+    // the file the tool inspects
+    let input = if let Some(artifact) = &artifact {
+        Some(artifact_file(artifact, matches.value_of("crate-type")).to_owned())
+    } else if let Some(file) = matches.value_of("file") {
+        if !Path::new(file).is_file() {
+            bail!("`{}` doesn't exist", file);
+        }
+        Some(env::current_dir()?.join(file))
+    } else if wants_artifact && !build {
+        let name = matches
+            .value_of("bin")
+            .or_else(|| matches.value_of("example"))
+            .unwrap_or("");
+        let target = target_flag.or_else(|| config.build_target());
+        let file = conventional_artifact(matches, name, target)?;
+        if !file.is_file() {
+            bail!(
+                "`{}` doesn't exist; build it first or drop `--no-build`",
+                file.display()
+            );
+        }
+        Some(file)
+    } else {
+        None
+    };
+    if let Some(input) = &input {
+        events.artifact(input)?;
+    }
+
+    run_artifact(
+        tool,
+        matches,
+        artifact,
+        input,
+        &target_dir,
+        target_flag,
+        out,
+        events,
+        terminal,
+    )
+}
+
+/// The arguments passed to the tool: the default ones of `CARGO_BINUTILS_<TOOL>_ARGS`, e.g.
+/// `CARGO_BINUTILS_OBJDUMP_ARGS`, followed by the ones given after `--`
+fn tool_args(tool: Tool, matches: &clap::ArgMatches) -> Result<Vec<String>, failure::Error> {
+    let env_var = format!("CARGO_BINUTILS_{}_ARGS", tool.name().to_uppercase());
+    let mut args = match env::var(&env_var) {
+        Ok(args) => util::split_args(&args)
+            .ok_or_else(|| format_err!("unterminated quote in `{}`", env_var))?,
+        Err(_) => vec![],
+    };
+    if let Some(values) = matches.values_of("args") {
+        args.extend(values.map(str::to_owned));
+    }
+
+    Ok(args)
+}
+
+/// The invocation of `tool` on `file`, if any: our flags translated into those of the tool,
+/// followed by `tool_args`. `run_artifact` runs it and `preview` prints it
+fn tool_invocation(
+    tool: Tool,
+    matches: &clap::ArgMatches,
+    ctxt: &Context,
+    file: Option<PathBuf>,
+    tool_args: &[&str],
+) -> Result<Command, failure::Error> {
+    // the user knows better
+    let arch = !matches.is_present("no-arch-name")
+        && !tool_args.iter().any(|arg| arg.starts_with("--triple"));
+    let mut lltool = ctxt.tool(tool, &ctxt.target, arch);
+
+    // Extra flags
+    match tool {
+        Tool::Readobj => {
+            // The default output style of `readobj` is JSON-like, which is not user friendly, so we
+            // change it to the human readable GNU style
+            lltool.arg("-elf-output-style=GNU");
+        }
+        Tool::Objdump => {
+            // `-l` does nothing on its own so `--line-numbers` disassembles too, unless the user
+            // already asked for it, e.g. with `-- -D`
+            let disassemble = (matches.is_present("disassemble")
+                || matches.is_present("line-numbers"))
+                && !tool_args
+                    .iter()
+                    .any(|arg| *arg == "-d" || *arg == "-D" || arg.starts_with("--disassemble"));
+            if disassemble && matches.is_present("all") {
+                lltool.arg("--disassemble-all");
+            } else if disassemble {
+                lltool.arg("-d");
+            }
+            if matches.is_present("line-numbers") {
+                lltool.arg("-l");
+            }
+
+            // the disassembler only knows the instructions of the CPU the code was compiled for
+            // if it's told, e.g. those of the FPU of a Cortex-M4F
+            let rustflags = rustflags(&ctxt.target)?;
+            let mcpu = matches.value_of("mcpu").or_else(|| llvm::mcpu(&rustflags));
+            let mattr = matches
+                .value_of("mattr")
+                .map(str::to_owned)
+                .or_else(|| llvm::mattr(&rustflags));
+            for (flag, value) in &[("mcpu", mcpu), ("mattr", mattr.as_deref())] {
+                let given = tool_args.iter().any(|arg| {
+                    arg.trim_start_matches('-')
+                        .strip_prefix(flag)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+                });
+                if let (Some(value), false) = (value, given) {
+                    lltool.arg(format!("--{}={}", flag, value));
+                }
+            }
+
+            for flag in &["start-address", "stop-address"] {
+                if let Some(addr) = matches.value_of(flag) {
+                    // already checked by `validate_address`
+                    let addr = parse_address(addr).unwrap();
+                    lltool.arg(format!("--{}={:#x}", flag, addr));
+                }
+            }
+        }
+        Tool::Objcopy | Tool::Strip => {
+            if let Some(symbols) = matches.values_of("keep-symbol") {
+                for symbol in symbols {
+                    lltool.arg(format!("--keep-symbol={}", symbol));
+                }
+            }
+
+            for flag in &["rename-section", "set-section-flags"] {
+                if let Some(values) = matches.values_of(flag) {
+                    for value in values {
+                        lltool.arg(format!("--{}", flag)).arg(value);
+                    }
+                }
+            }
+
+            let output_target = if matches.is_present("ihex") {
+                Some("ihex")
+            } else if matches.is_present("srec") {
+                Some("srec")
+            } else {
+                matches.value_of("output-target")
+            };
+            if let Some(format) = output_target {
+                lltool.args(["-O", format]);
+            }
+
+            for flag in &["strip-debug", "strip-all"] {
+                if matches.is_present(flag) {
+                    lltool.arg(format!("--{}", flag));
+                }
+            }
+        }
+        Tool::Size => {
+            // the System V format includes the address of each section and lists them one per line
+            let sections = matches.value_of("format") == Some("json");
+            if matches.is_present("regions")
+                || matches.is_present("section")
+                || matches.is_present("sort")
+                || sections
+            {
+                lltool.arg("-A");
+            }
+        }
+        Tool::Nm => {
+            for flag in &["numeric-sort", "no-sort", "size-sort", "undefined-only"] {
+                if matches.is_present(flag) {
+                    lltool.arg(format!("--{}", flag));
+                }
+            }
+
+            // on its own, `--size-sort` prints the size in place of the address
+            if matches.is_present("size-sort") {
+                lltool.arg("--print-size");
+            }
+        }
+        Tool::Ar | Tool::Cov | Tool::Cxxfilt | Tool::Dwarfdump | Tool::Profdata | Tool::Strings => {
+        }
+    }
+
+    // Artifact
+    let mut object = None;
+    if let Some(file) = file {
+        match tool {
+            // for some tools we change the CWD (current working directory) and
+            // make the artifact path relative. This makes the path that the
+            // tool will print easier to read. e.g. `libfoo.rlib` instead of
+            // `/home/user/rust/project/target/$T/debug/libfoo.rlib`.
+            // `--absolute-paths` opts out of this
+            Tool::Objdump | Tool::Nm | Tool::Readobj | Tool::Size
+                if !matches.is_present("absolute-paths") =>
+            {
+                lltool
+                    .current_dir(file.parent().unwrap())
+                    .arg(file.file_name().unwrap());
+            }
+            // `llvm-ar` and `llvm-cov` take the operation first, e.g. `llvm-ar t` or `llvm-cov
+            // report`, so the artifact goes after it, and `llvm-profdata` only takes it as the
+            // value of some flags
+            Tool::Ar | Tool::Cov | Tool::Profdata => object = Some(file),
+            _ => {
+                lltool.arg(&file);
+            }
+        }
+
+        // `objcopy <input> <output>` or `strip <input> -o <output>`
+        // (`profdata` gets it along with its command and the other tools write to stdout, which
+        // `run_to` redirects to the `--output` file)
+        if let Some(output) = matches.value_of("output") {
+            if tool == Tool::Strip {
+                lltool.arg("-o");
+            }
+            if tool == Tool::Objcopy || tool == Tool::Strip {
+                lltool.arg(output);
+            }
+        }
+    }
+
+    // User flags
+    if tool == Tool::Profdata {
+        let mut args = expand_input_lists(tool_args)?;
+        // `cargo profdata -- *.profraw` merges the profiles
+        let command = args.first().map(|arg| &**arg);
+        if command.is_some_and(|arg| !PROFDATA_COMMANDS.contains(&arg) && !is_help(arg)) {
+            args.insert(0, "merge".to_owned());
+        }
+        // `merge -o merged.profdata`; `show` and `overlap` write their report there too
+        if let Some(output) = matches.value_of("output") {
+            if args
+                .first()
+                .is_some_and(|arg| PROFDATA_COMMANDS.contains(&&**arg))
+            {
+                args.insert(1, format!("--output={}", output));
+            }
+        }
+        // e.g. `merge --binary-file={artifact}`
+        if let Some(file) = object.take() {
+            for arg in &mut args {
+                *arg = arg.replace("{artifact}", &file.to_string_lossy());
+            }
+        }
+        lltool.args(args);
+    } else if tool == Tool::Ar {
+        // e.g. `llvm-ar x libapp.rlib lib.rmeta`
+        let (operation, members) = tool_args.split_at(tool_args.len().min(1));
+        lltool.args(operation).args(object.take()).args(members);
+    } else {
+        lltool.args(tool_args);
+    }
+    if let Some(file) = object {
+        lltool.arg("-object").arg(file);
+    }
+
+    // the other tools don't color their output; `auto` leaves it to the tool, which checks if its
+    // output is a terminal
+    let color = match (tool, matches.value_of("color").unwrap()) {
+        (Tool::Cov, "always") => Some("--use-color=true"),
+        (Tool::Cov, "never") => Some("--use-color=false"),
+        (Tool::Objdump, "always") => Some("--disassembler-color=on"),
+        (Tool::Objdump, "never") => Some("--disassembler-color=off"),
+        _ => None,
+    };
+    lltool.args(color);
+
+    Ok(lltool)
+}
+
+// The part of `run_matches` that runs the tool on the `input` file, if any, and post-processes its
+// output. `artifact` is the artifact the file belongs to, if it was built
+#[allow(clippy::too_many_arguments)]
+fn run_artifact(
+    tool: Tool,
+    matches: &clap::ArgMatches,
+    artifact: Option<Artifact>,
+    input: Option<PathBuf>,
+    target_dir: &Path,
+    target_flag: Option<&str>,
+    out: &mut dyn Write,
+    events: &mut Events,
+    terminal: bool,
+) -> Result<i32, failure::Error> {
+    let name = tool.name();
+    let verbose = matches.is_present("verbose");
+
+    if let Some(artifact) = &artifact {
+        if matches.is_present("in-place") {
+            eprintln!(
+                "warning: modifying `{}` in place; Cargo still considers it fresh and won't \
+                 rebuild it until its sources change",
+                artifact_file(artifact, matches.value_of("crate-type")).display()
+            );
+        }
+    }
+
+    if let Some(artifact) = &artifact {
+        if matches.is_present("check-stale") {
+            let file = artifact_file(artifact, matches.value_of("crate-type"));
+            if let Some(source) = newer_source(artifact, file)? {
+                let message = format!(
+                    "`{}` is older than `{}`; it may be stale",
+                    file.display(),
+                    source.display()
+                );
+                if matches.is_present("fail-on-stale") {
+                    bail!("{}", message);
+                } else {
+                    eprintln!("warning: {}", message);
+                }
+            }
+        }
+    }
+
+    let tool_args = tool_args(tool, matches)?;
+    let tool_args = tool_args.iter().map(|arg| &**arg).collect::<Vec<_>>();
+
+    if tool == Tool::Cxxfilt {
+        return cxxfilt_to(&tool_args, demangle_options(tool, matches), out);
+    }
+
+    let ctxt = if let Some(artifact) = &artifact {
+        Context::from_artifact(artifact, target_dir, profile_dir(matches))?
+    } else {
+        project_context(target_flag)?
+    };
+
+    let message_format = match matches.value_of("message-format") {
+        Some(format) => format.parse()?,
+        None => MessageFormat::Human,
+    };
+
+    let regions = match matches.value_of("regions") {
+        Some(_) if message_format != MessageFormat::Human => {
+            bail!("`--regions` can only be used with the `human` message format")
+        }
+        Some(script) => Some(memory::regions(Path::new(script))?),
+        None => None,
+    };
+
+    // the file the tool inspects: the artifact, one of its members or its `.dSYM` bundle
+    let file = if let Some(input) = &input {
+        let mut file = input.clone();
+
+        if let Some(member) = matches.value_of("member") {
+            file = extract_member(&file, member, target_dir, verbose)?;
+        }
+
+        // on macOS the debug info is in `app.dSYM` rather than in `app`
+        if matches.is_present("dsym") {
+            let mut dsym = file.into_os_string();
+            dsym.push(".dSYM");
+            file = dsym.into();
+            if !file.exists() {
+                bail!(
+                    "`{}` doesn't exist; it's only produced for Apple targets, with \
+                     `split-debuginfo` set to `packed` (the default)",
+                    file.display()
+                );
+            }
+        }
+
+        if tool == Tool::Size && file.extension() == Some("wasm".as_ref()) {
+            bail!(
+                "`size` doesn't support WebAssembly modules like `{}`; try `cargo nm` or \
+                 `cargo objdump -- --section-headers` instead",
+                file.display()
+            );
+        }
+
+        Some(file)
+    } else {
+        None
+    };
+
+    // without flags `llvm-objdump` prints little more than the file format
+    if tool == Tool::Objdump
+        && tool_args.is_empty()
+        && !matches.is_present("disassemble")
+        && !matches.is_present("line-numbers")
+        && !matches.is_present("quiet")
+    {
+        eprintln!(
+            "note: no flags were passed to `llvm-objdump`; pass `-d` to disassemble or \
+             `-- --all-headers` to print the headers"
+        );
+    }
+
+    let mut lltool = tool_invocation(tool, matches, &ctxt, file, &tool_args)?;
+
+    if let Some(artifact) = &artifact {
+        if reads_debuginfo(tool, &tool_args) || matches.is_present("line-numbers") {
+            if artifact.profile.debuginfo == Some(0) {
+                eprintln!(
+                    "warning: `{}` was built without debug info; source and line information will \
+                     be missing from the output. Set `debug = true` in the profile to enable it",
+                    artifact.target.name
+                );
+            } else if let Some(file) = split_debuginfo(artifact) {
+                if tool == Tool::Dwarfdump && file.extension() == Some("dSYM".as_ref()) {
+                    if !matches.is_present("dsym") {
+                        eprintln!(
+                            "warning: the debug info of `{}` lives in `{}`; pass `--dsym` to \
+                             inspect it",
+                            artifact.target.name,
+                            file.display()
+                        );
+                    }
+                } else {
+                    eprintln!(
+                        "warning: the debug info of `{}` lives in `{}`; source and line \
+                         information may be missing from the output",
+                        artifact.target.name,
+                        file.display()
+                    );
+                }
+            }
+        }
+    }
+
+    // `llvm-strip` can't split the debug info out so that's done with `objcopy`, before and after
+    // stripping
+    let objcopy =
+        |flag: String, file: &Path, output: Option<&str>| -> Result<i32, failure::Error> {
+            let mut objcopy = ctxt.tool(Tool::Objcopy, &ctxt.target, false);
+            objcopy.arg(flag).arg(file).args(output);
+            if verbose {
+                eprintln!("{}", util::command_line(&objcopy));
+            }
+
+            Ok(util::exit_code(util::status(&mut objcopy)?))
+        };
+
+    if let Some(debug) = matches.value_of("only-keep-debug") {
+        let code = objcopy(
+            "--only-keep-debug".to_owned(),
+            input.as_ref().unwrap(),
+            Some(debug),
+        )?;
+        if code != 0 {
+            return Ok(code);
+        }
+    }
+
+    if verbose {
+        eprintln!(
+            "tool: {}",
+            search_tool(&format!("llvm-{}", name))?.display()
+        );
+        if let Some(input) = &input {
+            eprintln!("artifact: {}", input.display());
+        }
+        eprintln!("{}", util::command_line(&lltool));
+    }
+    events.tool_start(name, &lltool)?;
+
+    let demangle = demangle_options(tool, matches);
+
+    // without an artifact the input may be piped in, e.g. `cargo profdata merge --input-files=-`.
+    // The tool reads our stdin directly while we only collect its stdout, so there's no pipe in
+    // between that could fill up and deadlock
+    if input.is_none() {
+        lltool.stdin(Stdio::inherit());
+    }
+
+    // output that's not post-processed can be huge, e.g. that of `dwarfdump`, so it's passed along
+    // as it's produced rather than collected first; what's left to post-process is then empty
+    // the same goes for output that's only demangled, which is done a few lines at a time so that
+    // what a slow tool prints shows up right away, and what a crashing one printed is kept
+    let demangled_only = (matches!(tool, Tool::Objdump | Tool::Readobj)
+        || tool == Tool::Nm && message_format == MessageFormat::Human)
+        && !matches.is_present("annotate-symbols")
+        && !matches.is_present("color-symbols-by-section");
+    let output = if tool.passes_output_through() || demangled_only {
+        let mut child = util::spawn(lltool.stdout(Stdio::piped()))?;
+        let stdout = child.stdout.as_mut().unwrap();
+        if demangled_only {
+            postprocess::demangle_stream(stdout, out, demangle)?;
+        } else {
+            io::copy(stdout, out)?;
+        }
+        Output {
+            status: child.wait()?,
+            stdout: vec![],
+            stderr: vec![],
+        }
+    } else {
+        util::output(lltool.stderr(Stdio::inherit()))?
+    };
+
+    if let Some(debug) = matches.value_of("add-gnu-debuglink") {
+        if output.status.success() {
+            // the link goes in the stripped file
+            let file = match matches.value_of("output") {
+                Some(output) => Path::new(output),
+                None => input.as_ref().unwrap(),
+            };
+            let code = objcopy(format!("--add-gnu-debuglink={}", debug), file, None)?;
+            if code != 0 {
+                return Ok(code);
+            }
+        }
+    }
+
+    // `llvm-size` prints its warnings along with its output; the post-processing passes drop them,
+    // however many times they parse the output
+    if tool == Tool::Size {
+        for line in postprocess::diagnostics(&output.stdout) {
+            eprintln!("{}", line);
+        }
+    }
+
+    // post process output
+    let mut tool_stdout = match matches.values_of("section") {
+        Some(names) => postprocess::filter_sections(&output.stdout, &names.collect::<Vec<_>>()),
+        None => output.stdout[..].into(),
+    };
+    if let Some(key) = matches.value_of("sort") {
+        let key = match key {
+            "size" => SortKey::Size,
+            "name" => SortKey::Name,
+            _ => SortKey::Address,
+        };
+        tool_stdout = postprocess::sort_sections(&tool_stdout, key)
+            .into_owned()
+            .into();
+    }
+    let mut pp_output = postprocess(tool, &tool_stdout, message_format, demangle);
+    if tool == Tool::Size && matches.value_of("format") == Some("json") {
+        pp_output = postprocess::sections_json(&tool_stdout).into_owned().into();
+    }
+    if matches.is_present("human") {
+        pp_output = postprocess::humanize_sizes(&pp_output).into_owned().into();
+    }
+
+    // the Berkeley format only has totals; the sections come from a second, System V, pass
+    let sysv = lltool
+        .get_args()
+        .any(|arg| arg == "-A" || arg == "--format=sysv" || arg == "-format=sysv");
+    if tool == Tool::Size && message_format == MessageFormat::Json && !sysv {
+        let mut size = ctxt.tool(Tool::Size, &ctxt.target, false);
+        size.args(lltool.get_args()).arg("-A");
+        if let Some(dir) = lltool.get_current_dir() {
+            size.current_dir(dir);
+        }
+        if verbose {
+            eprintln!("{}", util::command_line(&size));
+        }
+
+        let sysv_output = util::output(size.stderr(Stdio::inherit()))?;
+        pp_output = postprocess::size_with_sections(&output.stdout, &sysv_output.stdout)
+            .into_owned()
+            .into();
+    }
+
+    if matches.is_present("annotate-symbols") {
+        if let Some(input) = &input {
+            let mut nm = ctxt.tool(Tool::Nm, &ctxt.target, false);
+            nm.arg(input);
+            if verbose {
+                eprintln!("{}", util::command_line(&nm));
+            }
+
+            let nm_output = util::output(nm.stderr(Stdio::inherit()))?;
+            let symbols =
+                postprocess::symbol_table(&postprocess::demangle(&nm_output.stdout, demangle));
+            pp_output = postprocess::annotate_symbols(&pp_output, &symbols)
+                .into_owned()
+                .into();
+        }
+    }
+
+    // like most tools, by default only color the output of terminals and honor `NO_COLOR`
+    let color = match matches.value_of("color").unwrap() {
+        "always" => true,
+        "never" => false,
+        _ => terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+    };
+    if matches.is_present("color-symbols-by-section")
+        && message_format == MessageFormat::Human
+        && color
+    {
+        pp_output = postprocess::color_by_section(&pp_output)
+            .into_owned()
+            .into();
+    }
+
+    out.write_all(&pp_output)?;
+
+    if let Some(regions) = &regions {
+        let sections = postprocess::sections(&output.stdout);
+        out.write_all(memory::utilization(regions, &sections).as_bytes())?;
+    }
+
+    if let Some(artifact) = &artifact {
+        // sizes of unoptimized builds are rarely what users are interested in
+        if tool == Tool::Size && artifact.profile.opt_level == "0" && !matches.is_present("quiet") {
+            eprintln!("note: inspecting debug build; pass --release for optimized sizes");
+        }
+    }
+
+    if !output.status.success() {
+        return Ok(util::exit_code(output.status));
+    }
+
+    if matches.is_present("flash-max") || matches.is_present("ram-max") {
+        let (flash, ram) = match postprocess::budget_usage(&output.stdout) {
+            Some(usage) => usage,
+            None => bail!("`--flash-max` and `--ram-max` require the default (Berkeley) format"),
+        };
+
+        let mut exceeded = false;
+        for (name, used, flag) in &[("flash", flash, "flash-max"), ("RAM", ram, "ram-max")] {
+            if let Some(max) = matches.value_of(flag) {
+                // already checked by `validate_size`
+                let max = memory::parse_number(max).unwrap();
+                if *used > max {
+                    eprintln!(
+                        "error: {} usage ({}) exceeds the budget of {}",
+                        name,
+                        postprocess::human_size(*used),
+                        postprocess::human_size(max)
+                    );
+                    exceeded = true;
+                }
+            }
+        }
+
+        if exceeded {
+            return Ok(BUDGET_EXCEEDED);
+        }
+    }
+
+    Ok(0)
+}
+
+/// How the symbols in the output of `tool` are demangled, as requested by the flags
+fn demangle_options(tool: Tool, matches: &clap::ArgMatches) -> Demangle {
+    Demangle {
+        collapse_closures: matches.is_present("collapse-closures"),
+        cpp: true,
+        backend: match matches.value_of("demangle-backend") {
+            Some("itanium") => DemangleBackend::Itanium,
+            _ => DemangleBackend::Rustc,
+        },
+        // already checked by `validate_threads`
+        threads: matches
+            .value_of("demangle-threads")
+            .map_or(1, |threads| threads.parse().unwrap()),
+        raw: matches.is_present("no-demangle"),
+        member_headers: tool == Tool::Nm,
+    }
+}
+
+/// `rust-cxxfilt`, which demangles the symbols like `cargo cxxfilt` does as there's no
+/// `llvm-cxxfilt` to forward to
+pub fn cxxfilt() -> Result<i32, failure::Error> {
+    // NOTE(`skip`) the first argument is the name of the binary (e.g. `rust-cxxfilt`)
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(|arg| &**arg).collect::<Vec<_>>();
+
+    cxxfilt_to(&args, Demangle::default(), &mut io::stdout().lock())
+}
+
+// Demangles the symbols in `args` or, if there are none, the lines read from stdin, which are
+// otherwise printed as they are
+fn cxxfilt_to(
+    args: &[&str],
+    options: Demangle,
+    out: &mut dyn Write,
+) -> Result<i32, failure::Error> {
+    if let Some(&flag) = args.iter().find(|arg| arg.starts_with('-')) {
+        if is_help(flag) {
+            writeln!(
+                out,
+                "USAGE: cxxfilt [<symbol>...]\n\nDemangles the Rust and C++ symbols passed as \
+                 arguments or, if there are none, those in the lines read from stdin"
+            )?;
+            return Ok(0);
+        }
+
+        bail!(
+            "`cxxfilt` is not `llvm-cxxfilt`, which the Rust toolchain doesn't ship, and doesn't \
+             support `{}`",
+            flag
+        );
+    }
+
+    if args.is_empty() {
+        postprocess::demangle_stream(io::stdin().lock(), out, options)?;
+    } else {
+        for symbol in args {
+            out.write_all(&postprocess::demangle(
+                format!("{}\n", symbol).as_bytes(),
+                options,
+            ))?;
+        }
+    }
+
+    Ok(0)
+}
 
 pub fn forward(tool: &str) -> Result<i32, failure::Error> {
     let path = search_tool(tool)?;
 
     // NOTE(`skip`) the first argument is the name of the binary (e.g. `rust-nm`)
-    let status = Command::new(path).args(env::args().skip(1)).status()?;
+    let status = util::status(Command::new(path).args(env::args().skip(1)))?;
 
-    if status.success() {
-        Ok(0)
-    } else {
-        Ok(status.code().unwrap_or(101))
+    Ok(util::exit_code(status))
+}
+
+// `$RUSTC`, e.g. a wrapper or the compiler of another toolchain, or `rustc`
+fn rustc() -> Command {
+    Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()))
+}
+
+fn sysroot() -> Result<PathBuf, failure::Error> {
+    let sysroot = String::from_utf8(util::output(rustc().arg("--print").arg("sysroot"))?.stdout)?;
+
+    Ok(PathBuf::from(sysroot.trim()))
+}
+
+/// The directory given by `CARGO_BINUTILS_LLVM_DIR`, e.g. `/usr/bin` for the LLVM tools of a
+/// distribution, which replaces the `llvm-tools-preview` component
+fn llvm_dir() -> Option<PathBuf> {
+    env::var_os("CARGO_BINUTILS_LLVM_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The directory where the `llvm-tools-preview` component installs the LLVM tools, unless
+/// overridden by `CARGO_BINUTILS_LLVM_DIR`
+fn bindir() -> Result<PathBuf, failure::Error> {
+    if let Some(dir) = llvm_dir() {
+        return Ok(dir);
     }
+
+    // finding it takes two `rustc` invocations
+    static BINDIR: OnceLock<PathBuf> = OnceLock::new();
+
+    if let Some(bindir) = BINDIR.get() {
+        return Ok(bindir.clone());
+    }
+
+    let host = rustc_version::version_meta()?.host;
+    let bindir = sysroot()?
+        .join("lib")
+        .join("rustlib")
+        .join(host)
+        .join("bin");
+
+    Ok(BINDIR.get_or_init(|| bindir).clone())
 }
 
+/// The LLVM tools that have a subcommand but that the `llvm-tools-preview` component doesn't ship;
+/// they have to come from `CARGO_BINUTILS_LLVM_DIR`
+const UNSHIPPED: [&str; 2] = ["llvm-dwarfdump", "llvm-strings"];
+
 fn search_tool(tool: &str) -> Result<PathBuf, failure::Error> {
-    let sysroot = String::from_utf8(
-        Command::new("rustc")
-            .arg("--print")
-            .arg("sysroot")
-            .output()?
-            .stdout,
-    )?;
+    let path = bindir()?.join(&*exe(tool));
+    if path.exists() {
+        return Ok(path);
+    }
 
-    for entry in WalkDir::new(sysroot.trim()) {
+    if let Some(dir) = llvm_dir() {
+        bail!(
+            "`{}` is not in `{}`, the `CARGO_BINUTILS_LLVM_DIR`; the tools in that directory must \
+             be named `llvm-*`, e.g. `llvm-size`",
+            exe(tool),
+            dir.display()
+        );
+    }
+
+    if UNSHIPPED.contains(&tool) {
+        bail!(
+            "the `llvm-tools-preview` component doesn't ship `{}`; install the LLVM tools that \
+             match the LLVM version of your toolchain (see `rustc -vV`), e.g. with your package \
+             manager, and point `CARGO_BINUTILS_LLVM_DIR` to their directory",
+            tool
+        );
+    }
+
+    // the sysroot has thousands of files so walking it is the last resort
+    for entry in WalkDir::new(sysroot()?) {
         let entry = entry?;
 
         if entry.file_name() == &*exe(tool) {
@@ -490,8 +3591,85 @@ fn search_tool(tool: &str) -> Result<PathBuf, failure::Error> {
         }
     }
 
-    bail!(
-        "`llvm-tools-preview` component is missing or empty. Install it with `rustup component \
-         add llvm-tools-preview`"
-    );
+    if component_installed() {
+        bail!(
+            "the `llvm-tools-preview` component is installed but it does not include `{}`; \
+             install the LLVM tools that match the LLVM version of your toolchain (see `rustc \
+             -vV`), e.g. with your package manager, and point `CARGO_BINUTILS_LLVM_DIR` to their \
+             directory",
+            tool
+        );
+    } else if rustup_installed() {
+        bail!(
+            "`llvm-tools-preview` component is missing or empty. Install it with `rustup component \
+             add llvm-tools-preview`, or pass `--yes` to the `cargo` subcommand to install it"
+        );
+    } else {
+        bail!(
+            "`{}` is not in the sysroot (`{}`) and `rustup`, which installs the \
+             `llvm-tools-preview` component, is not on PATH; install the LLVM tools that match \
+             the LLVM version of your toolchain (see `rustc -vV`), e.g. with your package manager, \
+             and point `CARGO_BINUTILS_LLVM_DIR` to their directory",
+            tool,
+            sysroot()?.display()
+        );
+    }
+}
+
+// Whether the `llvm-tools-preview` component is installed, i.e. whether there are LLVM tools in
+// its directory
+fn component_installed() -> bool {
+    let entries = match bindir().and_then(|dir| Ok(fs::read_dir(dir)?)) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .any(|entry| entry.file_name().to_string_lossy().starts_with("llvm-"))
+}
+
+fn rustup_installed() -> bool {
+    Command::new("rustup")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Offers to install the `llvm-tools-preview` component if the LLVM tool behind `tool` is missing.
+/// It's only installed if `yes` or the user agrees when asked on a terminal; otherwise running the
+/// tool reports that it's missing
+fn ensure_installed(tool: Tool, yes: bool) -> Result<(), failure::Error> {
+    if tool.is_builtin()
+        || UNSHIPPED.contains(&&*format!("llvm-{}", tool.name()))
+        || llvm_dir().is_some()
+        || search_tool(&format!("llvm-{}", tool.name())).is_ok()
+        || component_installed()
+        || !rustup_installed()
+    {
+        return Ok(());
+    }
+
+    if !yes {
+        if !(io::stdin().is_terminal() && io::stderr().is_terminal()) {
+            return Ok(());
+        }
+
+        eprint!("the `llvm-tools-preview` component is not installed; install it now? [y/N] ");
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Ok(());
+        }
+    }
+
+    let mut rustup = Command::new("rustup");
+    rustup.args(["component", "add", "llvm-tools-preview"]);
+    if !util::status(&mut rustup)?.success() {
+        bail!("`rustup component add llvm-tools-preview` failed");
+    }
+
+    Ok(())
 }
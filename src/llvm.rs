@@ -1,5 +1,9 @@
+use std::borrow::Cow;
+
 use rustc_cfg::Cfg;
 
+use crate::Endian::{self, Big, Little};
+
 // Here we map Rust arches to LLVM arches
 //
 // Rust knows these arches as of 1.28 (from librustc_target/abi/call/mod.rs)
@@ -53,17 +57,19 @@ use rustc_cfg::Cfg;
 // - wasm64
 // - x86
 // - x86-64
+//
+// NOTE the ABI is not part of LLVM's architecture name: `arm-unknown-linux-gnueabi` and
+// `arm-unknown-linux-gnueabihf` are both `arm`. The float ABI reaches `objdump` through the
+// `--triple`, which is the full target name (see `triple`), and `cfg` is the output of
+// `rustc --print cfg` for the full target name as well
 pub fn arch_name<'a>(cfg: &'a Cfg, target: &'a str) -> &'a str {
-    const BIG: &str = "big";
-    const LITTLE: &str = "little";
-
-    let endian = &*cfg.target_endian;
+    let endian = Endian::of(cfg);
     let arch = &*cfg.target_arch;
 
     if target.starts_with("thumb") {
         // no way to tell from `--print cfg` that the target is thumb only so we
         // completely rely on the target name here
-        if endian == BIG {
+        if endian == Big {
             "thumbeb"
         } else {
             "thumb"
@@ -71,16 +77,16 @@ pub fn arch_name<'a>(cfg: &'a Cfg, target: &'a str) -> &'a str {
     } else {
         match (arch, endian) {
             // non standard endianness
-            ("aarch64", BIG) => "aarch64_be",
-            ("arm", BIG) => "armeb",
-            ("mips", LITTLE) => "mipsel",
-            ("mips64", LITTLE) => "mips64el",
-            ("powerpc64", LITTLE) => "ppc64le",
-            ("sparc", LITTLE) => "sparcel",
+            ("aarch64", Big) => "aarch64_be",
+            ("arm", Big) => "armeb",
+            ("mips", Little) => "mipsel",
+            ("mips64", Little) => "mips64el",
+            ("powerpc64", Little) => "ppc64le",
+            ("sparc", Little) => "sparcel",
 
             // names that match
             ("powerpc", _) => "ppc32",
-            ("powerpc64", BIG) => "ppc64",
+            ("powerpc64", Big) => "ppc64",
             ("sparc64", _) => "sparcv9",
             ("s390x", _) => "systemz",
             ("x86_64", _) => "x86-64",
@@ -90,3 +96,78 @@ pub fn arch_name<'a>(cfg: &'a Cfg, target: &'a str) -> &'a str {
         }
     }
 }
+
+// Here we map Rust targets to LLVM triples. Most Rust targets are valid LLVM triples; the
+// exceptions are:
+//
+// - RISC-V targets, which encode the ISA extensions in the architecture (e.g. `riscv32imac`)
+// - custom target specifications, whose LLVM triple is only known by reading the specification
+pub fn triple(target: &str) -> Option<Cow<'_, str>> {
+    if target.ends_with(".json") {
+        return None;
+    }
+
+    let (arch, rest) = match target.find('-') {
+        Some(pos) => (&target[..pos], &target[pos..]),
+        None => (target, ""),
+    };
+
+    if arch.starts_with("riscv32") {
+        Some(format!("riscv32{}", rest).into())
+    } else if arch.starts_with("riscv64") {
+        Some(format!("riscv64{}", rest).into())
+    } else {
+        Some(target.into())
+    }
+}
+
+/// The `-C target-cpu` among `rustflags`, e.g. `cortex-m4`, which is passed to the disassembler as
+/// `--mcpu`. `native` is left out: it means the CPU of the machine that did the build
+pub fn mcpu(rustflags: &[String]) -> Option<&str> {
+    codegen_options(rustflags)
+        .filter(|(name, _)| *name == "target-cpu")
+        .map(|(_, cpu)| cpu)
+        .last()
+        .filter(|cpu| *cpu != "native")
+}
+
+/// The `-C target-feature`s among `rustflags`, e.g. `+fp-armv8,-neon`, which are passed to the
+/// disassembler as `--mattr`. Features that only `rustc` knows about, like `crt-static`, are left
+/// out as LLVM would warn about them
+pub fn mattr(rustflags: &[String]) -> Option<String> {
+    let features = codegen_options(rustflags)
+        .filter(|(name, _)| *name == "target-feature")
+        .flat_map(|(_, features)| features.split(','))
+        .filter(|feature| {
+            let name = feature.trim_start_matches(['+', '-']);
+            !name.is_empty() && !RUSTC_FEATURES.contains(&name)
+        })
+        .collect::<Vec<_>>();
+
+    if features.is_empty() {
+        None
+    } else {
+        Some(features.join(","))
+    }
+}
+
+// Target features that `rustc` handles itself rather than passing them to LLVM
+const RUSTC_FEATURES: [&str; 1] = ["crt-static"];
+
+// The `-C name=value` options among `rustflags`, in all the forms `rustc` accepts: `-C opt=val`,
+// `-Copt=val`, `--codegen opt=val` and `--codegen=opt=val`
+fn codegen_options(rustflags: &[String]) -> impl Iterator<Item = (&str, &str)> {
+    let mut flags = rustflags.iter().map(|flag| &**flag);
+    let mut options = vec![];
+    while let Some(flag) = flags.next() {
+        let option = match flag {
+            "-C" | "--codegen" => flags.next(),
+            _ => flag
+                .strip_prefix("-C")
+                .or_else(|| flag.strip_prefix("--codegen=")),
+        };
+        options.extend(option.and_then(|option| option.split_once('=')));
+    }
+
+    options.into_iter()
+}
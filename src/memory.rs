@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::Path;
+
+use failure::{bail, format_err};
+use regex::Regex;
+
+use crate::postprocess::{self, Section};
+
+/// A memory region declared in the `MEMORY` command of a linker script
+#[derive(Debug, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub origin: u64,
+    pub length: u64,
+}
+
+impl Region {
+    fn contains(&self, address: u64) -> bool {
+        address >= self.origin && address - self.origin < self.length
+    }
+}
+
+/// Parses the `MEMORY` command of the GNU ld style linker script at `path`, e.g.
+///
+/// ``` text
+/// MEMORY
+/// {
+///   FLASH (rx) : ORIGIN = 0x08000000, LENGTH = 256K
+///   RAM : ORIGIN = 0x20000000, LENGTH = 64K
+/// }
+/// ```
+pub fn regions(path: &Path) -> Result<Vec<Region>, failure::Error> {
+    let script = fs::read_to_string(path)
+        .map_err(|e| format_err!("couldn't read `{}`: {}", path.display(), e))?;
+
+    let comments = Regex::new(r"(?s)/\*.*?\*/").expect("BUG: Malformed Regex");
+    let script = comments.replace_all(&script, "");
+
+    let memory = Regex::new(r"(?s)\bMEMORY\s*\{(.*?)\}").expect("BUG: Malformed Regex");
+    let region = Regex::new(
+        r"(?i)(\w+)\s*(?:\([^)]*\))?\s*:\s*(?:ORIGIN|org|o)\s*=\s*([^,]+?)\s*,\s*(?:LENGTH|len|l)\s*=\s*([^\s,]+)",
+    )
+    .expect("BUG: Malformed Regex");
+
+    let body = match memory.captures(&script) {
+        Some(cs) => cs.get(1).unwrap().as_str().to_owned(),
+        None => bail!("`{}` has no `MEMORY` command", path.display()),
+    };
+
+    let regions = region
+        .captures_iter(&body)
+        .map(|cs| {
+            let number = |i: usize| {
+                let s = cs.get(i).unwrap().as_str();
+                parse_number(s).ok_or_else(|| {
+                    format_err!(
+                        "unsupported expression `{}` in `{}`; only plain numbers, optionally \
+                         followed by `K` or `M`, are supported",
+                        s,
+                        path.display()
+                    )
+                })
+            };
+
+            Ok(Region {
+                name: cs.get(1).unwrap().as_str().to_owned(),
+                origin: number(2)?,
+                length: number(3)?,
+            })
+        })
+        .collect::<Result<Vec<_>, failure::Error>>()?;
+
+    if regions.is_empty() {
+        bail!(
+            "the `MEMORY` command in `{}` declares no regions",
+            path.display()
+        );
+    }
+
+    Ok(regions)
+}
+
+/// Numbers in linker scripts: decimal or `0x` prefixed hexadecimal, with an optional `K` or `M`
+/// multiplier
+pub fn parse_number(s: &str) -> Option<u64> {
+    let (s, multiplier) = if let Some(s) = s.strip_suffix(['K', 'k']) {
+        (s, 1024)
+    } else if let Some(s) = s.strip_suffix(['M', 'm']) {
+        (s, 1024 * 1024)
+    } else {
+        (s, 1)
+    };
+
+    let n = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()?
+    } else {
+        s.parse().ok()?
+    };
+
+    n.checked_mul(multiplier)
+}
+
+// Sections that are not loaded in memory; these have an address of `0`, which some devices use as
+// the start of their Flash memory
+fn is_allocated(section: &Section) -> bool {
+    !(section.name.starts_with(".debug")
+        || section.name == ".comment"
+        || section.name == ".ARM.attributes"
+        || section.name == ".symtab"
+        || section.name == ".strtab"
+        || section.name == ".shstrtab")
+}
+
+/// Reports how much of each region is used by the `sections`, going by the address at which each
+/// section sits at runtime
+// NOTE sections that are copied into RAM at startup (e.g. `.data`) also occupy space in Flash but
+// `size -A` only reports their runtime address so they are only accounted for in RAM
+pub fn utilization(regions: &[Region], sections: &[Section]) -> String {
+    let width = regions.iter().map(|r| r.name.len()).max().unwrap_or(0);
+
+    let mut s = String::new();
+    for region in regions {
+        let used: u64 = sections
+            .iter()
+            .filter(|section| is_allocated(section) && region.contains(section.addr))
+            .map(|section| section.size)
+            .sum();
+
+        let percentage = if region.length == 0 {
+            0.
+        } else {
+            used as f64 * 100. / region.length as f64
+        };
+
+        s.push_str(&format!(
+            "{:width$} {} / {} ({:.1}%)\n",
+            format!("{}:", region.name),
+            postprocess::human_size(used),
+            postprocess::human_size(region.length),
+            percentage,
+            width = width + 1,
+        ));
+    }
+
+    s
+}
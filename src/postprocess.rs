@@ -1,33 +1,426 @@
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::str;
+use std::sync::OnceLock;
+use std::thread;
 
 use regex::{Captures, Regex};
-use rustc_demangle;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::MessageFormat;
 
 // Here we post process the output of some tools to improve. If the output of the tool is not valid
 // UTF-8 then we don't touch it.
 
+/// Tweaks to the rendering of demangled symbols
+#[derive(Clone, Copy, Debug)]
+pub struct Demangle {
+    /// Render chains of nested closures, e.g. `foo::{{closure}}::{{closure}}`, as
+    /// `foo::{{closure}}x2`
+    pub collapse_closures: bool,
+    /// Also demangle C++ symbols, the default. Rust's legacy mangling reuses the Itanium prefix
+    /// (`_Z`) so symbols are demangled as Rust symbols first and only the remaining ones as C++
+    /// symbols
+    pub cpp: bool,
+    /// How Rust symbols are rendered
+    pub backend: DemangleBackend,
+    /// Number of threads that demangle the output, in chunks of lines; `0` and `1` demangle it on
+    /// the current thread
+    pub threads: usize,
+    /// Leave all the symbols mangled
+    pub raw: bool,
+    /// Leave the headers that `nm` prints before the symbols of each archive member untouched
+    pub member_headers: bool,
+}
+
+impl Default for Demangle {
+    fn default() -> Self {
+        Demangle {
+            collapse_closures: false,
+            cpp: true,
+            backend: DemangleBackend::default(),
+            threads: 1,
+            raw: false,
+            member_headers: false,
+        }
+    }
+}
+
+/// The demangler of Rust symbols
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DemangleBackend {
+    /// `rustc-demangle`, which renders symbols like the compiler does, e.g. `<T as Trait>::method`
+    #[default]
+    Rustc,
+    /// A generic Itanium ABI demangler, which leaves the Rust specific escapes of the legacy
+    /// mangling in place, e.g. `$LT$T$u20$as$u20$Trait$GT$::method`
+    Itanium,
+}
+
+impl DemangleBackend {
+    fn demangle(self, symbol: &str) -> String {
+        match self {
+            DemangleBackend::Rustc => rustc_demangle::demangle(symbol).to_string(),
+            DemangleBackend::Itanium => {
+                demangle_cpp(symbol).unwrap_or_else(|| rustc_demangle::demangle(symbol).to_string())
+            }
+        }
+    }
+}
+
 // This pass demangles *all* the Rust symbols in the input
-pub fn demangle(bytes: &[u8]) -> Cow<[u8]> {
-    let re = Regex::new(r#"_Z.+?E\b"#).expect("BUG: Malformed Regex");
+pub fn demangle(bytes: &[u8], options: Demangle) -> Cow<'_, [u8]> {
+    // compiled once as `demangle_stream` calls this for every few lines
+    static RE: OnceLock<Regex> = OnceLock::new();
+    static CPP: OnceLock<Regex> = OnceLock::new();
+
+    // NOTE legacy (`_Z`) and v0 (`_R`) mangled symbols only contain alphanumerics, `_`, `$` and
+    // `.`; this keeps a match from spanning the quotes, brackets, etc. that some tools wrap symbols
+    // in, or several words of a line
+    let re = RE.get_or_init(|| {
+        Regex::new(r#"_Z[\w$.]+?E\b|\b_?_R[0-9A-Za-z_$.]+"#).expect("BUG: Malformed Regex")
+    });
+    // the leading underscore of `__Z` is the one macOS adds to every symbol
+    let cpp = CPP.get_or_init(|| Regex::new(r"\b_?_Z[\w.$]+").expect("BUG: Malformed Regex"));
+
+    if options.raw {
+        return bytes.into();
+    }
 
     if let Ok(text) = str::from_utf8(bytes) {
-        match re.replace_all(text, |cs: &Captures| {
-            format!("{}", rustc_demangle::demangle(cs.get(0).unwrap().as_str()))
-        }) {
-            Cow::Borrowed(s) => s.as_bytes().into(),
-            Cow::Owned(s) => s.into_bytes().into(),
+        if !(re.is_match(text) || options.cpp && cpp.is_match(text)) {
+            return bytes.into();
         }
+
+        if options.threads > 1 {
+            let chunks = line_chunks(text, options.threads);
+            let demangled = thread::scope(|scope| {
+                let handles = chunks
+                    .iter()
+                    .map(|&chunk| scope.spawn(move || demangle_lines(chunk, options, re, cpp)))
+                    .collect::<Vec<_>>();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("BUG: demangling thread panicked"))
+                    .collect::<String>()
+            });
+
+            return demangled.into_bytes().into();
+        }
+
+        demangle_lines(text, options, re, cpp).into_bytes().into()
     } else {
         bytes.into()
     }
 }
 
-// This pass turns the addresses in the output of `size -A` into hexadecimal format
-pub fn size(bytes: &[u8]) -> Cow<[u8]> {
+/// Demangles the output of a tool, read from `input`, as it's produced and writes it to `out`. Lines
+/// are demangled in batches: whatever whole lines can be read without waiting for the tool
+pub fn demangle_stream(input: impl Read, out: &mut dyn Write, options: Demangle) -> io::Result<()> {
+    let mut input = BufReader::with_capacity(64 * 1024, input);
+    let mut batch = vec![];
+    loop {
+        let n = input.read_until(b'\n', &mut batch)?;
+        if n == 0 || input.buffer().is_empty() {
+            out.write_all(&demangle(&batch, options))?;
+            out.flush()?;
+            batch.clear();
+        }
+
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+// Splits `text` into `n` chunks of about the same length that end at line boundaries
+fn line_chunks(text: &str, n: usize) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut rest = text;
+    let len = text.len().div_ceil(n);
+    while !rest.is_empty() {
+        let end = match rest.get(len..).and_then(|tail| tail.find('\n')) {
+            Some(newline) => len + newline + 1,
+            None => rest.len(),
+        };
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+
+    chunks
+}
+
+// The per-line work of `demangle`
+fn demangle_lines(text: &str, options: Demangle, re: &Regex, cpp: &Regex) -> String {
+    let mut s = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if options.member_headers && is_member_header(line) {
+            s.push_str(line);
+        } else {
+            let line = re.replace_all(line, |cs: &Captures| {
+                let symbol = cs.get(0).unwrap().as_str();
+                // only `rustc-demangle` knows the v0 mangling, including the extra leading
+                // underscore of macOS; what merely looks like a v0 symbol, e.g. `_RESET`, is left
+                // as it is
+                let symbol = if symbol.starts_with("_R") || symbol.starts_with("__R") {
+                    match rustc_demangle::try_demangle(symbol) {
+                        Ok(demangled) => demangled.to_string(),
+                        Err(_) => symbol.to_owned(),
+                    }
+                } else {
+                    options.backend.demangle(symbol)
+                };
+
+                if options.collapse_closures {
+                    collapse_closures(&symbol).into_owned()
+                } else {
+                    symbol
+                }
+            });
+
+            if options.cpp {
+                s.push_str(&cpp.replace_all(&line, |cs: &Captures| {
+                    let symbol = cs.get(0).unwrap().as_str();
+                    demangle_cpp(symbol).unwrap_or_else(|| symbol.to_owned())
+                }));
+            } else {
+                s.push_str(&line);
+            }
+        }
+    }
+
+    s
+}
+
+// Demangles a C++ symbol, keeping the extra leading underscore of macOS symbols out of the way
+fn demangle_cpp(symbol: &str) -> Option<String> {
+    let mangled = symbol.strip_prefix('_').filter(|s| s.starts_with("_Z"));
+    let demangled = cpp_demangle::Symbol::new(mangled.unwrap_or(symbol))
+        .ok()?
+        .demangle()
+        .ok()?;
+
+    Some(demangled)
+}
+
+// Collapses chains of two or more closures into a single `::{{closure}}xN`. Both the legacy
+// (`{{closure}}`) and the v0 (`{closure#0}`) renderings are collapsed
+fn collapse_closures(symbol: &str) -> Cow<'_, str> {
+    // compiled once as this is called for every demangled symbol
+    static RE: OnceLock<Regex> = OnceLock::new();
+
+    let re = RE.get_or_init(|| {
+        Regex::new(r"(?:::\{\{closure\}\}|::\{closure(?:#\d+)?\}){2,}")
+            .expect("BUG: Malformed Regex")
+    });
+
+    re.replace_all(symbol, |cs: &Captures| {
+        let chain = cs.get(0).unwrap().as_str();
+        format!("::{{{{closure}}}}x{}", chain.matches("closure").count())
+    })
+}
+
+// Whether `line` is the header that precedes the symbols of an archive member in the output of
+// `nm`, e.g. `libfoo.rlib[foo-0123456789abcdef.foo.1a2b3c4d-cgu.0.rcgu.o]:` or, on macOS,
+// `libfoo.a(foo.o):`. These are file names and must be left untouched. Only a single word that
+// ends in the member name qualifies: `objdump -l` prints `; _ZN3foo3barE():` lines, which are
+// symbols
+fn is_member_header(line: &str) -> bool {
+    let line = line.trim_end();
+
+    !line.contains(char::is_whitespace)
+        && (line.ends_with("]:") && line.contains('[')
+            || line.ends_with(".o):") && line.contains('('))
+}
+
+// Whether `line` is a diagnostic emitted by the tool rather than part of its regular output, e.g.
+// `llvm-size: warning: 'app': unknown section type`
+fn is_diagnostic(line: &str) -> bool {
+    line.starts_with("warning:")
+        || line.starts_with("error:")
+        || line.contains(": warning:")
+        || line.contains(": error:")
+}
+
+/// The diagnostics interleaved with the output of the tool, e.g. those of `llvm-size`, which the
+/// passes below drop; the caller prints them, once
+pub fn diagnostics(bytes: &[u8]) -> Vec<&str> {
+    match str::from_utf8(bytes) {
+        Ok(text) => text.lines().filter(|line| is_diagnostic(line)).collect(),
+        Err(_) => vec![],
+    }
+}
+
+// Drops the diagnostics from the output of the tool; see `diagnostics`
+fn without_diagnostics(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter(|line| !is_diagnostic(line))
+}
+
+// This pass post-processes the output of `size` into the requested format
+pub fn size(bytes: &[u8], format: MessageFormat) -> Cow<'_, [u8]> {
+    match format {
+        MessageFormat::Human => size_human(bytes),
+        MessageFormat::Json | MessageFormat::Csv => match str::from_utf8(bytes) {
+            Ok(text) => {
+                let rows = size_rows(text);
+                if format == MessageFormat::Json {
+                    json(&rows)
+                } else {
+                    csv(&rows)
+                }
+                .into_bytes()
+                .into()
+            }
+            Err(_) => bytes.into(),
+        },
+    }
+}
+
+/// The order `sort_sections` puts the sections in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    /// Largest first
+    Size,
+    Name,
+    Address,
+}
+
+/// Sorts the sections of the System V (`-A`) output of `size`. Lines that don't parse as a
+/// section, e.g. headers and totals, stay where they are and only the sections between them are
+/// sorted
+pub fn sort_sections(bytes: &[u8], key: SortKey) -> Cow<'_, [u8]> {
+    fn number(field: &str) -> Option<u64> {
+        match field.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => field.parse().ok(),
+        }
+    }
+
+    fn flush(rows: &mut Vec<(&str, u64, u64, &str)>, key: SortKey, s: &mut String) {
+        match key {
+            SortKey::Size => rows.sort_by_key(|row| Reverse(row.1)),
+            SortKey::Name => rows.sort_by(|a, b| a.0.cmp(b.0)),
+            SortKey::Address => rows.sort_by_key(|row| row.2),
+        }
+        for (_, _, _, line) in rows.drain(..) {
+            s.push_str(line);
+        }
+    }
+
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return bytes.into(),
+    };
+
+    let mut s = String::with_capacity(text.len());
+    let mut in_table = false;
+    let mut rows = vec![];
+    for line in text.split_inclusive('\n') {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        match fields.as_slice() {
+            ["section", ..] => {
+                in_table = true;
+                s.push_str(line);
+                continue;
+            }
+            [name, size, addr] if in_table => {
+                if let (Some(size), Some(addr)) = (number(size), number(addr)) {
+                    rows.push((*name, size, addr, line));
+                    continue;
+                }
+            }
+            _ => in_table = false,
+        }
+
+        // when the line ends a block of sections or can't be parsed
+        flush(&mut rows, key, &mut s);
+        s.push_str(line);
+    }
+    flush(&mut rows, key, &mut s);
+
+    s.into_bytes().into()
+}
+
+// This pass keeps only the `names` sections in the System V (`-A`) output of `size` and updates the
+// `Total` lines to match
+pub fn filter_sections<'a>(bytes: &'a [u8], names: &[&str]) -> Cow<'a, [u8]> {
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return bytes.into(),
+    };
+
+    let mut s = String::with_capacity(text.len());
+    let mut in_table = false;
+    let mut total = 0;
+    for line in text.split_inclusive('\n') {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        match fields.as_slice() {
+            ["section", ..] => {
+                in_table = true;
+                total = 0;
+                s.push_str(line);
+            }
+            ["Total", size] if in_table => {
+                in_table = false;
+                // keep the number in the same column
+                let start = line.rfind(size).unwrap();
+                s.push_str(&line[..start]);
+                s.push_str(&total.to_string());
+                s.push_str(&line[start + size.len()..]);
+            }
+            [name, size, _] if in_table => {
+                if names.contains(name) {
+                    total += size.parse::<u64>().unwrap_or(0);
+                    s.push_str(line);
+                }
+            }
+            _ => s.push_str(line),
+        }
+    }
+
+    s.into_bytes().into()
+}
+
+// This pass demangles the output of `nm` and then turns it into the requested format
+pub fn nm(bytes: &[u8], format: MessageFormat, options: Demangle) -> Cow<'_, [u8]> {
+    let options = Demangle {
+        member_headers: true,
+        ..options
+    };
+    let demangled = demangle(bytes, options);
+
+    match format {
+        MessageFormat::Human => demangled,
+        MessageFormat::Json | MessageFormat::Csv => match str::from_utf8(&demangled) {
+            Ok(text) => {
+                let symbols = text.lines().filter_map(Symbol::parse).collect::<Vec<_>>();
+                if format == MessageFormat::Json {
+                    serde_json::to_string_pretty(&symbols).expect("BUG: unserializable symbols")
+                        + "\n"
+                } else {
+                    let rows = symbols.iter().map(Symbol::to_row).collect::<Vec<_>>();
+                    csv(&rows)
+                }
+                .into_bytes()
+                .into()
+            }
+            Err(_) => demangled.into_owned().into(),
+        },
+    }
+}
+
+// This pass turns the addresses in the output of `size -A` into hexadecimal format. Diagnostics
+// interleaved with the table are dropped so they don't get in the way of the table
+fn size_human(bytes: &[u8]) -> Cow<'_, [u8]> {
     if let Ok(text) = str::from_utf8(bytes) {
-        let mut s = text
-            .lines()
+        let mut s = without_diagnostics(text)
             .map(|line| -> Cow<str> {
                 match line
                     .split_whitespace()
@@ -39,7 +432,7 @@ pub fn size(bytes: &[u8]) -> Cow<[u8]> {
                     Some((needle, addr)) if line.starts_with('.') => {
                         let pos = line.rfind(needle).unwrap();
                         let hex_addr = format!("{:#x}", addr);
-                        let start = pos + needle.as_bytes().len() - hex_addr.as_bytes().len();
+                        let start = pos + needle.len() - hex_addr.len();
 
                         format!("{}{}", &line[..start], hex_addr).into()
                     }
@@ -57,3 +450,501 @@ pub fn size(bytes: &[u8]) -> Cow<[u8]> {
         bytes.into()
     }
 }
+
+/// The JSON output of `size` in the default (Berkeley) format with, in each row, a `sections` array
+/// taken from the System V (`-A`) output of the same invocation, e.g.
+/// `{ "name": ".vector_table", "size": 1024, "address": 134217728 }`
+// NOTE `size` doesn't report the load address (LMA) of the sections so that's not included
+pub fn size_with_sections<'a>(berkeley: &'a [u8], sysv: &[u8]) -> Cow<'a, [u8]> {
+    let (text, sysv) = match (str::from_utf8(berkeley), str::from_utf8(sysv)) {
+        (Ok(text), Ok(sysv)) => (text, sysv),
+        _ => return berkeley.into(),
+    };
+
+    let mut rows = size_rows(text);
+    let sections = size_rows(sysv);
+    let single = rows.len() == 1;
+    for row in &mut rows {
+        let file = row
+            .get("filename")
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+        let sections = sections
+            .iter()
+            .filter(|section| {
+                single || section.get("file").and_then(Value::as_str) == file.as_deref()
+            })
+            .filter_map(|section| serde_json::to_value(Section::from_row(section)?).ok())
+            .collect::<Vec<_>>();
+        row.insert("sections".to_string(), Value::from(sections));
+    }
+
+    json(&rows).into_bytes().into()
+}
+
+// Parses the tables in the output of `size`, in either the Berkeley (default) or the System V
+// (`-A`) format, into one object per row. The Berkeley header is `text data bss dec hex filename`;
+// the System V output is a `file  :` line followed by a `section size addr` header. The `Total`
+// line of the latter is not included as it can be derived from the rows.
+fn size_rows(text: &str) -> Vec<Map<String, Value>> {
+    let mut rows = vec![];
+    let mut header: Option<Vec<&str>> = None;
+    let mut file = None;
+
+    for line in without_diagnostics(text) {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        match fields.first() {
+            Some(&"text") | Some(&"section") => {
+                header = Some(fields);
+                continue;
+            }
+            _ => {}
+        }
+
+        match &header {
+            Some(header) if header.len() == fields.len() => {
+                let mut row = Map::new();
+                if let Some(file) = file {
+                    row.insert("file".to_string(), Value::from(file));
+                }
+                // binaries without data may lack some columns; report those as zero so that all
+                // the Berkeley rows have the same shape
+                if header[0] == "text" {
+                    for key in &["text", "data", "bss"] {
+                        row.insert(key.to_string(), Value::from(0));
+                    }
+                }
+                for (key, field) in header.iter().zip(&fields) {
+                    row.insert(key.to_string(), size_value(key, field));
+                }
+                rows.push(row);
+            }
+            _ => {
+                header = None;
+                if line.trim_end().ends_with(':') {
+                    file = Some(line.trim_end().trim_end_matches(':').trim_end());
+                }
+            }
+        }
+    }
+
+    rows
+}
+
+// Numeric fields are turned into numbers, the `hex` column of the Berkeley format is always
+// hexadecimal even though it has no `0x` prefix
+fn size_value(key: &str, field: &str) -> Value {
+    let number = if key == "hex" {
+        u64::from_str_radix(field, 16).ok()
+    } else if let Some(hex) = field.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        field.parse().ok()
+    };
+
+    number
+        .map(Value::from)
+        .unwrap_or_else(|| Value::from(field))
+}
+
+/// Flash (`text` + `data`) and RAM (`data` + `bss`) usage, in bytes, according to the Berkeley
+/// (default) output of `size`. Returns `None` if the output is in another format
+pub fn budget_usage(bytes: &[u8]) -> Option<(u64, u64)> {
+    let rows = size_rows(str::from_utf8(bytes).ok()?);
+    if rows.is_empty() {
+        return None;
+    }
+
+    let mut flash = 0;
+    let mut ram = 0;
+    for row in rows {
+        let field = |key| row.get(key).and_then(Value::as_u64);
+        let (text, data, bss) = (field("text")?, field("data")?, field("bss")?);
+        flash += text + data;
+        ram += data + bss;
+    }
+
+    Some((flash, ram))
+}
+
+// This pass colors the names of the symbols in the (human) output of `nm` according to the kind of
+// section they live in
+pub fn color_by_section(bytes: &[u8]) -> Cow<'_, [u8]> {
+    const RESET: &str = "\x1b[0m";
+
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return bytes.into(),
+    };
+
+    let mut s = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n');
+        let color = Symbol::parse(content)
+            .filter(|symbol| content.ends_with(&symbol.name))
+            .map(|symbol| (symbol_color(symbol.kind), symbol.name.len()));
+
+        match color {
+            Some((Some(color), len)) => {
+                let (head, name) = content.split_at(content.len() - len);
+                s.push_str(head);
+                s.push_str(color);
+                s.push_str(name);
+                s.push_str(RESET);
+                s.push_str(&line[content.len()..]);
+            }
+            _ => s.push_str(line),
+        }
+    }
+
+    s.into_bytes().into()
+}
+
+// ANSI color for the symbols of each `nm` kind: text, data, bss, weak and undefined
+fn symbol_color(kind: char) -> Option<&'static str> {
+    match kind {
+        'T' | 't' => Some("\x1b[32m"),
+        'D' | 'd' | 'R' | 'r' | 'G' | 'g' => Some("\x1b[34m"),
+        'B' | 'b' | 'S' | 's' => Some("\x1b[35m"),
+        'W' | 'w' | 'V' | 'v' => Some("\x1b[33m"),
+        'U' => Some("\x1b[31m"),
+        _ => None,
+    }
+}
+
+/// Maps the addresses of the defined symbols in the (demangled) output of `nm` to their names
+pub fn symbol_table(bytes: &[u8]) -> BTreeMap<u64, String> {
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return BTreeMap::new(),
+    };
+
+    text.lines()
+        .filter_map(Symbol::parse)
+        .filter_map(|symbol| Some((symbol.address?, symbol.name)))
+        .collect()
+}
+
+// This pass appends the name of the symbol to the lines of the disassembly whose operands
+// reference the address of a symbol in `symbols`, e.g. `bl 0x8000400  # <app::main>`. Lines
+// where `objdump` already names the symbol are left alone
+pub fn annotate_symbols<'a>(bytes: &'a [u8], symbols: &BTreeMap<u64, String>) -> Cow<'a, [u8]> {
+    let re = Regex::new(r"\b0x([0-9a-fA-F]+)\b").expect("BUG: Malformed Regex");
+
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return bytes.into(),
+    };
+
+    let mut s = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n');
+
+        // the operands come after the address of the instruction, e.g. `  8000404: ...`
+        let names = match content.find(':') {
+            Some(pos) => re
+                .captures_iter(&content[pos..])
+                .filter_map(|cs| u64::from_str_radix(cs.get(1).unwrap().as_str(), 16).ok())
+                .filter_map(|addr| symbols.get(&addr))
+                .filter(|name| !content.contains(&format!("<{}>", name)))
+                .fold(vec![], |mut names, name| {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                    names
+                }),
+            None => vec![],
+        };
+
+        s.push_str(content);
+        for name in names {
+            s.push_str(&format!("  # <{}>", name));
+        }
+        s.push_str(&line[content.len()..]);
+    }
+
+    s.into_bytes().into()
+}
+
+/// A section listed by `size -A`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Section {
+    pub name: String,
+    pub size: u64,
+    #[serde(rename = "address")]
+    pub addr: u64,
+}
+
+impl Section {
+    // A row of the System V output, as parsed by `size_rows`
+    fn from_row(row: &Map<String, Value>) -> Option<Self> {
+        Some(Section {
+            name: row.get("section")?.as_str()?.to_owned(),
+            size: row.get("size")?.as_u64()?,
+            addr: row.get("addr")?.as_u64()?,
+        })
+    }
+}
+
+/// The sections of the System V (`-A`) output of `size` as a JSON array of
+/// `{ "name": .., "size": .., "address": .. }` objects, the same as the `sections` of
+/// `size_with_sections`
+pub fn sections_json(bytes: &[u8]) -> Cow<'_, [u8]> {
+    match str::from_utf8(bytes) {
+        Ok(_) => (serde_json::to_string_pretty(&sections(bytes))
+            .expect("BUG: unserializable sections")
+            + "\n")
+            .into_bytes()
+            .into(),
+        Err(_) => bytes.into(),
+    }
+}
+
+/// Parses the sections out of the System V (`-A`) output of `size`
+pub fn sections(bytes: &[u8]) -> Vec<Section> {
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return vec![],
+    };
+
+    size_rows(text)
+        .iter()
+        .filter_map(Section::from_row)
+        .collect()
+}
+
+/// Formats the sizes in the human output of `size`, Berkeley or System V, with `human_size` and
+/// realigns the columns. Addresses, the `hex` column, file names and header rows are left as they
+/// are
+pub fn humanize_sizes(bytes: &[u8]) -> Cow<'_, [u8]> {
+    // `line` without its first `n` fields
+    fn rest(line: &str, n: usize) -> &str {
+        let mut rest = line.trim();
+        for _ in 0..n {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            rest = rest[end..].trim_start();
+        }
+        rest
+    }
+
+    fn size(field: &str) -> Option<String> {
+        field.parse().ok().map(human_size)
+    }
+
+    let text = match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return bytes.into(),
+    };
+
+    let mut s = String::with_capacity(text.len());
+    let mut lines = text.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let header = line.split_whitespace().collect::<Vec<_>>();
+        let berkeley = header == ["text", "data", "bss", "dec", "hex", "filename"];
+        let sysv = header == ["section", "size", "addr"];
+        if !berkeley && !sysv {
+            s.push_str(line);
+            continue;
+        }
+
+        let mut table = vec![header
+            .iter()
+            .map(|field| field.to_string())
+            .collect::<Vec<_>>()];
+        while let Some(line) = lines.peek() {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let row = if berkeley && fields.len() >= 6 {
+                fields[..4]
+                    .iter()
+                    .map(|field| size(field))
+                    .chain(vec![
+                        Some(fields[4].to_owned()),
+                        Some(rest(line, 5).to_owned()),
+                    ])
+                    .collect::<Option<Vec<_>>>()
+            } else if sysv && fields.len() == 3 {
+                size(fields[1]).map(|size| vec![fields[0].to_owned(), size, fields[2].to_owned()])
+            } else if sysv && fields.len() == 2 && fields[0] == "Total" {
+                size(fields[1]).map(|size| vec![fields[0].to_owned(), size, String::new()])
+            } else {
+                None
+            };
+
+            match row {
+                Some(row) => table.push(row),
+                None => break,
+            }
+            lines.next();
+        }
+
+        let columns = table[0].len();
+        let widths = (0..columns)
+            .map(|i| table.iter().map(|row| row[i].len()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        for row in &table {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                if berkeley {
+                    // numbers right aligned, as `size` does, and the file name last
+                    if i == columns - 1 {
+                        line.push_str(cell);
+                    } else {
+                        line.push_str(&format!("{:>width$}\t", cell, width = widths[i]));
+                    }
+                } else if i == 0 {
+                    line.push_str(&format!("{:width$}", cell, width = widths[i]));
+                } else {
+                    line.push_str(&format!("   {:>width$}", cell, width = widths[i]));
+                }
+            }
+            s.push_str(line.trim_end());
+            s.push('\n');
+        }
+    }
+
+    s.into_bytes().into()
+}
+
+/// Formats a number of bytes using binary (1024-based) units, e.g. `48.0KiB`
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+/// The symbols listed in the output of `nm`, demangled
+pub fn symbols(bytes: &[u8], options: Demangle) -> Vec<Symbol> {
+    let options = Demangle {
+        member_headers: true,
+        ..options
+    };
+    match str::from_utf8(&demangle(bytes, options)) {
+        Ok(text) => text.lines().filter_map(Symbol::parse).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// A symbol listed by `nm`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Symbol {
+    /// Missing for undefined symbols
+    pub address: Option<u64>,
+    /// Only known if `nm` is passed `-S`
+    pub size: Option<u64>,
+    /// `nm`'s one letter type, e.g. `T` for code; lowercase for local symbols
+    pub kind: char,
+    /// Demangled name
+    pub name: String,
+}
+
+impl Symbol {
+    // Parses a line of `nm` output: `$address [$size] $kind $name`, where the address (and size)
+    // are missing for undefined symbols. Other lines (e.g. the headers of archive members) are
+    // ignored
+    fn parse(line: &str) -> Option<Symbol> {
+        fn hex(field: &str) -> Option<u64> {
+            u64::from_str_radix(field, 16).ok()
+        }
+
+        fn kind(field: &str) -> Option<char> {
+            let mut chars = field.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii_alphabetic() || c == '?' => Some(c),
+                _ => None,
+            }
+        }
+
+        // splits off the next field, keeping any whitespace in the (demangled) name intact
+        fn field(s: &str) -> Option<(&str, &str)> {
+            let s = s.trim_start();
+            let end = s.find(char::is_whitespace)?;
+            Some((&s[..end], s[end..].trim_start()))
+        }
+
+        let symbol = |address, size, kind, name: &str| {
+            if name.is_empty() {
+                None
+            } else {
+                Some(Symbol {
+                    address,
+                    size,
+                    kind,
+                    name: name.to_string(),
+                })
+            }
+        };
+
+        let (first, rest) = field(line)?;
+        if let Some(kind) = kind(first) {
+            return symbol(None, None, kind, rest);
+        }
+
+        let address = hex(first)?;
+        let (second, rest) = field(rest)?;
+        if let Some(kind) = kind(second) {
+            return symbol(Some(address), None, kind, rest);
+        }
+
+        let size = hex(second)?;
+        let (third, rest) = field(rest)?;
+        symbol(Some(address), Some(size), kind(third)?, rest)
+    }
+
+    fn to_row(&self) -> Map<String, Value> {
+        let mut row = Map::new();
+        row.insert("address".to_string(), self.address.into());
+        row.insert("size".to_string(), self.size.into());
+        row.insert("kind".to_string(), self.kind.to_string().into());
+        row.insert("name".to_string(), self.name.clone().into());
+        row
+    }
+}
+
+fn json(rows: &[Map<String, Value>]) -> String {
+    serde_json::to_string_pretty(rows).expect("BUG: unserializable rows") + "\n"
+}
+
+// Renders `rows` as CSV, using the keys of the first row as the header
+fn csv(rows: &[Map<String, Value>]) -> String {
+    fn escape(field: &str) -> Cow<'_, str> {
+        if field.contains([',', '"', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\"")).into()
+        } else {
+            field.into()
+        }
+    }
+
+    let mut s = String::new();
+    if let Some(first) = rows.first() {
+        let header = first.keys().map(|k| escape(k)).collect::<Vec<_>>();
+        s.push_str(&header.join(","));
+        s.push('\n');
+
+        for row in rows {
+            let fields = row
+                .values()
+                .map(|value| match value {
+                    Value::Null => "".into(),
+                    Value::String(s) => escape(s).into_owned(),
+                    value => value.to_string(),
+                })
+                .collect::<Vec<_>>();
+            s.push_str(&fields.join(","));
+            s.push('\n');
+        }
+    }
+
+    s
+}
@@ -0,0 +1,73 @@
+//! Experimental server mode: `--server`
+//!
+//! The `Context` is built once and then requests are read from stdin, one per line:
+//!
+//! ``` text
+//! <tool> <artifact-path> <args...>
+//! ```
+//!
+//! e.g. `size target/thumbv7m-none-eabi/release/app -A`. The post-processed output of each
+//! request is written to stdout and followed by a `--- <exit-code>` line. Errors that prevent the
+//! tool from running are reported on stderr and their exit code is `-1`.
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use failure::format_err;
+
+use crate::{util, Context, Tool};
+
+/// Serves requests until stdin is closed, writing the responses to `out`
+pub fn serve(ctxt: &Context, verbose: bool, out: &mut dyn Write) -> Result<i32, failure::Error> {
+    let stdin = io::stdin();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let code = match request(ctxt, &line, verbose) {
+            Ok((code, output)) => {
+                out.write_all(&output)?;
+                code
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                -1
+            }
+        };
+
+        writeln!(out, "--- {}", code)?;
+        out.flush()?;
+    }
+
+    Ok(0)
+}
+
+// Runs a single request and returns the exit code and the post-processed output of the tool
+fn request(ctxt: &Context, line: &str, verbose: bool) -> Result<(i32, Vec<u8>), failure::Error> {
+    let mut words = line.split_whitespace();
+
+    let name = words.next().unwrap();
+    let tool = Tool::all()
+        .iter()
+        .cloned()
+        .find(|tool| tool.name() == name)
+        .ok_or_else(|| format_err!("unknown tool `{}`", name))?;
+    let artifact = words.next().ok_or_else(|| {
+        format_err!("missing artifact path; expected `<tool> <artifact-path> <args...>`")
+    })?;
+
+    let artifact = Path::new(artifact);
+    let args = words.collect::<Vec<_>>();
+
+    if verbose {
+        let lltool = ctxt.tool_command(tool, Some(artifact), &args);
+        eprintln!("{}", util::command_line(&lltool));
+    }
+
+    let output = ctxt.run_tool(tool, Some(artifact), &args)?;
+
+    Ok((output.exit_code, output.stdout))
+}
@@ -0,0 +1,143 @@
+use std::borrow::Cow;
+use std::io;
+use std::process::{Child, Command, ExitStatus, Output};
+
+use failure::format_err;
+
+/// Spawns `command`
+pub fn spawn(command: &mut Command) -> Result<Child, failure::Error> {
+    command.spawn().map_err(|e| error(command, e))
+}
+
+/// Runs `command` to completion and collects its output
+pub fn output(command: &mut Command) -> Result<Output, failure::Error> {
+    command.output().map_err(|e| error(command, e))
+}
+
+/// Runs `command` to completion
+pub fn status(command: &mut Command) -> Result<ExitStatus, failure::Error> {
+    command.status().map_err(|e| error(command, e))
+}
+
+/// The exit code to report for a process that exited with `status`: its own or, if it was killed
+/// by a signal, `128` plus the number of the signal, as shells do
+pub fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+/// The Levenshtein distance between `a` and `b`: how many characters have to be inserted, removed
+/// or replaced to turn one into the other
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    // the distances between the prefix of `a` seen so far and each prefix of `b`
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replace = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replace.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Renders `command` as a line that can be pasted into a POSIX shell, e.g. `cd target/debug &&
+/// rust-size -A app`; unlike the `Debug` representation, only the arguments that need it are quoted
+pub fn command_line(command: &Command) -> String {
+    let mut line = String::new();
+    if let Some(dir) = command.get_current_dir() {
+        line.push_str("cd ");
+        line.push_str(&quote(&dir.to_string_lossy()));
+        line.push_str(" && ");
+    }
+
+    line.push_str(&quote(&command.get_program().to_string_lossy()));
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&quote(&arg.to_string_lossy()));
+    }
+
+    line
+}
+
+// Quotes `arg` for a POSIX shell, if needed
+fn quote(arg: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.into()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''")).into()
+    }
+}
+
+/// Splits `s` into arguments like a POSIX shell would, minus expansions: arguments are separated by
+/// whitespace, quotes group words and a backslash escapes the next character (except within single
+/// quotes). Returns `None` if a quote is not closed
+pub fn split_args(s: &str) -> Option<Vec<String>> {
+    let mut args = vec![];
+    let mut arg = None::<String>;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => arg.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => arg.push(chars.next()?),
+                        c => arg.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    arg.get_or_insert_with(String::new).push(c);
+                }
+            }
+            c if c.is_whitespace() => args.extend(arg.take()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+
+    Some(args)
+}
+
+// Turns the error of spawning `command` into one that says which command couldn't be run
+fn error(command: &Command, e: io::Error) -> failure::Error {
+    let program = command.get_program().to_string_lossy();
+    let line = command_line(command);
+
+    if e.kind() == io::ErrorKind::NotFound {
+        format_err!(
+            "could not find `{}` (while running `{}`); ensure it's installed and on PATH",
+            program,
+            line
+        )
+    } else {
+        format_err!("couldn't run `{}`: {}", line, e)
+    }
+}